@@ -1,30 +1,58 @@
 use anyhow::{anyhow, Context};
-use once_cell::sync::Lazy;
+use once_cell::sync::{Lazy, OnceCell};
 use reqwest::{header, Client, StatusCode, Url};
 
 use super::Lang;
 
 fn base_url() -> anyhow::Result<Url> {
-    Url::parse(super::config().base_url()).context("Invalid base url")
-}
-macro_rules! build_url {
-    ($($segment:tt)/+) => {{
-        let ret: anyhow::Result<Url> = base_url().and_then(|mut url| {
-            url.path_segments_mut()
-                .map_err(|()| anyhow!("Invalid base url"))?
-                .extend(&[$($segment),+]);
-            Ok(url)
-        });
-        ret
-    }};
+    Url::parse(&super::config().base_url()).context("Invalid base url")
 }
 
 static CLIENT: Lazy<Client> = Lazy::new(client);
 
 const USER_AGENT: &str = concat!(env!("CARGO_PKG_NAME"), "/", env!("CARGO_PKG_VERSION"));
+
+/// A long-lived API token, for CI where logging in isn't an option. It's read from
+/// `RUMBLEBOT_API_TOKEN`, or from the file that `RUMBLEBOT_API_TOKEN_FILE` points to
+pub fn api_token() -> anyhow::Result<Option<&'static str>> {
+    static TOKEN: OnceCell<Option<String>> = OnceCell::new();
+    let token = TOKEN.get_or_try_init(|| {
+        let token = match std::env::var_os("RUMBLEBOT_API_TOKEN_FILE") {
+            Some(path) => Some(std::fs::read_to_string(&path).with_context(|| {
+                format!(
+                    "Couldn't read RUMBLEBOT_API_TOKEN_FILE {}",
+                    std::path::Path::new(&path).display()
+                )
+            })?),
+            None => std::env::var("RUMBLEBOT_API_TOKEN").ok(),
+        };
+        let token = token.map(|t| t.trim().to_owned()).filter(|t| !t.is_empty());
+        if let Some(token) = &token {
+            bearer(token)?;
+        }
+        Ok::<_, anyhow::Error>(token)
+    })?;
+    Ok(token.as_deref())
+}
+
+/// The `Authorization` header for an API token
+fn bearer(token: &str) -> anyhow::Result<header::HeaderValue> {
+    header::HeaderValue::from_str(&format!("Bearer {}", token))
+        .context("The API token has characters that can't be sent in a header")
+}
+
 fn client() -> Client {
     let mut builder = Client::builder().user_agent(USER_AGENT);
-    if let Some(ref jwt) = super::config().auth_key {
+    // the token is checked before any account command, and ignored if it can't be read otherwise
+    let token = api_token()
+        .ok()
+        .flatten()
+        .and_then(|token| bearer(token).ok());
+    if let Some(token) = token {
+        let mut headers = header::HeaderMap::with_capacity(1);
+        headers.append(header::AUTHORIZATION, token);
+        builder = builder.default_headers(headers);
+    } else if let Some(jwt) = super::config().auth_key() {
         let mut headers = header::HeaderMap::with_capacity(1);
         headers.append(
             reqwest::header::COOKIE,
@@ -50,6 +78,35 @@ pub struct RobotInfo {
     // userId: usize,
 }
 
+/// One of a user's robots, as listed by `robots`
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RobotSummary {
+    pub id: usize,
+    pub name: String,
+    pub lang: Lang,
+    /// Whether the robot has published code that's playing in matches
+    #[serde(default)]
+    pub published: bool,
+    /// `None` until the robot has played enough matches to be rated
+    #[serde(default)]
+    pub rating: Option<f64>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RobotDetails {
+    #[serde(flatten)]
+    pub summary: RobotSummary,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// Timestamps, as sent by the server
+    #[serde(default)]
+    pub created: Option<String>,
+    #[serde(default)]
+    pub modified: Option<String>,
+}
+
 async fn handle_response(res: reqwest::Response) -> anyhow::Result<reqwest::Response> {
     match res.status() {
         StatusCode::OK => Ok(res),
@@ -71,85 +128,269 @@ async fn handle_response(res: reqwest::Response) -> anyhow::Result<reqwest::Resp
     }
 }
 
-pub async fn robot_info(user: &str, robot: &str) -> anyhow::Result<Option<RobotInfo>> {
-    let res = CLIENT
-        .get(build_url!("api" / "get-robot" / user / robot)?)
-        .send()
-        .await?;
-    if res.status() == StatusCode::NOT_FOUND {
-        return Ok(None);
+/// The server to make requests to, which is the configured one outside of tests
+struct Api {
+    client: Client,
+    base_url: Url,
+}
+
+impl Api {
+    fn new() -> anyhow::Result<Self> {
+        Ok(Self {
+            client: CLIENT.clone(),
+            base_url: base_url()?,
+        })
+    }
+
+    fn url(&self, segments: &[&str]) -> anyhow::Result<Url> {
+        let mut url = self.base_url.clone();
+        url.path_segments_mut()
+            .map_err(|()| anyhow!("Invalid base url"))?
+            .extend(segments);
+        Ok(url)
+    }
+
+    async fn robot_info(&self, user: &str, robot: &str) -> anyhow::Result<Option<RobotInfo>> {
+        let res = self
+            .client
+            .get(self.url(&["api", "get-robot", user, robot])?)
+            .send()
+            .await?;
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let info = handle_response(res).await?.json().await?;
+        Ok(info)
+    }
+
+    async fn robots(&self, user: &str) -> anyhow::Result<Option<Vec<RobotSummary>>> {
+        let res = self
+            .client
+            .get(self.url(&["api", "get-user-robots", user])?)
+            .send()
+            .await?;
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let robots = handle_response(res).await?.json().await?;
+        Ok(robots)
+    }
+
+    async fn robot_details(&self, user: &str, robot: &str) -> anyhow::Result<Option<RobotDetails>> {
+        let res = self
+            .client
+            .get(self.url(&["api", "get-robot-details", user, robot])?)
+            .send()
+            .await?;
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let details = handle_response(res).await?.json().await?;
+        Ok(details)
+    }
+
+    async fn robot_code(&self, id: usize) -> anyhow::Result<Option<String>> {
+        let res = self
+            .client
+            .get(self.url(&["api", "get-published-code", &id.to_string()])?)
+            .send()
+            .await?;
+        let code: String = handle_response(res).await?.json().await?;
+        let code = if code.is_empty() { None } else { Some(code) };
+        Ok(code)
+    }
+
+    async fn working_code(&self, id: usize) -> anyhow::Result<Option<String>> {
+        let res = self
+            .client
+            .get(self.url(&["api", "get-robot-code", &id.to_string()])?)
+            .send()
+            .await?;
+        if res.status() == StatusCode::NOT_FOUND {
+            return Ok(None);
+        }
+        let code: String = handle_response(res).await?.json().await?;
+        let code = if code.is_empty() { None } else { Some(code) };
+        Ok(code)
+    }
+
+    async fn authenticate(&self, username: &str, password: &str) -> anyhow::Result<String> {
+        #[derive(serde::Serialize)]
+        struct Request<'a> {
+            username: &'a str,
+            password: &'a str,
+        }
+        let res = self
+            .client
+            .post(self.url(&["api", "login"])?)
+            .json(&Request { username, password })
+            .send()
+            .await
+            .context("Couldn't send authentication request")?;
+        handle_response(res)
+            .await?
+            .cookies()
+            .find(|c| c.name() == "PLAY_SESSION")
+            .map(|c| c.value().to_owned())
+            .ok_or_else(|| {
+                anyhow!(
+                    "Authentication response returned with OK but did not set PLAY_SESSION cookie"
+                )
+            })
+    }
+
+    async fn create(&self, lang: Lang, name: &str) -> anyhow::Result<RobotInfo> {
+        #[derive(serde::Serialize)]
+        struct Request<'a> {
+            lang: &'a str,
+            name: &'a str,
+        }
+        let res = self
+            .client
+            .post(self.url(&["api", "create-robot"])?)
+            .json(&Request {
+                lang: lang.as_ref(),
+                name,
+            })
+            .send()
+            .await?;
+        let info = handle_response(res).await?.json().await?;
+        Ok(info)
+    }
+
+    async fn update_code(&self, id: usize, code: &str) -> anyhow::Result<()> {
+        #[derive(serde::Serialize)]
+        struct Request<'a> {
+            code: &'a str,
+        }
+        let res = self
+            .client
+            .post(self.url(&["api", "update-robot-code", &id.to_string()])?)
+            .json(&Request { code })
+            .send()
+            .await?;
+        handle_response(res).await.map(drop)
+    }
+
+    async fn whoami(&self) -> anyhow::Result<(String, usize)> {
+        let res = self
+            .client
+            .get(self.url(&["api", "whoami"])?)
+            .send()
+            .await?;
+        let ret = handle_response(res).await?.json().await?;
+        Ok(ret)
     }
-    let info = handle_response(res).await?.json().await?;
-    Ok(info)
+}
+
+pub async fn robot_info(user: &str, robot: &str) -> anyhow::Result<Option<RobotInfo>> {
+    Api::new()?.robot_info(user, robot).await
+}
+
+pub async fn robots(user: &str) -> anyhow::Result<Option<Vec<RobotSummary>>> {
+    Api::new()?.robots(user).await
+}
+
+pub async fn robot_details(user: &str, robot: &str) -> anyhow::Result<Option<RobotDetails>> {
+    Api::new()?.robot_details(user, robot).await
 }
 
 pub async fn robot_code(id: usize) -> anyhow::Result<Option<String>> {
-    let res = CLIENT
-        .get(build_url!(
-            "api" / "get-published-code" / (&id.to_string())
-        )?)
-        .send()
-        .await?;
-    let code: String = handle_response(res).await?.json().await?;
-    let code = if code.is_empty() { None } else { Some(code) };
-    Ok(code)
+    Api::new()?.robot_code(id).await
+}
+
+/// The robot's current code, as last written by `update_code`, which may not be published yet.
+/// Only the robot's owner can get it
+pub async fn working_code(id: usize) -> anyhow::Result<Option<String>> {
+    Api::new()?.working_code(id).await
 }
 
 pub async fn authenticate(username: &str, password: &str) -> anyhow::Result<String> {
-    #[derive(serde::Serialize)]
-    struct Request<'a> {
-        username: &'a str,
-        password: &'a str,
-    }
-    let res = CLIENT
-        .post(build_url!("api" / "login")?)
-        .json(&Request { username, password })
-        .send()
-        .await
-        .context("Couldn't send authentication request")?;
-    handle_response(res)
-        .await?
-        .cookies()
-        .find(|c| c.name() == "PLAY_SESSION")
-        .map(|c| c.value().to_owned())
-        .ok_or_else(|| {
-            anyhow!("Authentication response returned with OK but did not set PLAY_SESSION cookie")
-        })
+    Api::new()?.authenticate(username, password).await
 }
 
 pub async fn create(lang: Lang, name: &str) -> anyhow::Result<RobotInfo> {
-    #[derive(serde::Serialize)]
-    struct Request<'a> {
-        lang: &'a str,
-        name: &'a str,
-    }
-    let res = CLIENT
-        .post(build_url!("api" / "create-robot")?)
-        .json(&Request {
-            lang: lang.as_ref(),
-            name,
-        })
-        .send()
-        .await?;
-    let info = handle_response(res).await?.json().await?;
-    Ok(info)
+    Api::new()?.create(lang, name).await
 }
 
 pub async fn update_code(id: usize, code: &str) -> anyhow::Result<()> {
-    #[derive(serde::Serialize)]
-    struct Request<'a> {
-        code: &'a str,
-    }
-    let res = CLIENT
-        .post(build_url!("api" / "update-robot-code" / (&id.to_string()))?)
-        .json(&Request { code })
-        .send()
-        .await?;
-    handle_response(res).await.map(drop)
+    Api::new()?.update_code(id, code).await
 }
 
 pub async fn whoami() -> anyhow::Result<(String, usize)> {
-    let res = CLIENT.get(build_url!("api" / "whoami")?).send().await?;
-    let ret = handle_response(res).await?.json().await?;
-    Ok(ret)
+    Api::new()?.whoami().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use warp::Filter;
+
+    /// Start a server with canned responses, and an `Api` that makes requests to it
+    fn mock_server() -> Api {
+        let robots = warp::path!("api" / "get-user-robots" / "alice").map(|| {
+            warp::reply::json(&serde_json::json!([
+                {"id": 1, "name": "rush", "lang": "Python", "published": true, "rating": 1510.4},
+                {"id": 2, "name": "wip", "lang": "Javascript"},
+            ]))
+        });
+        let details = warp::path!("api" / "get-robot-details" / "alice" / "rush").map(|| {
+            warp::reply::json(&serde_json::json!({
+                "id": 1,
+                "name": "rush",
+                "lang": "Python",
+                "published": true,
+                "rating": 1510.4,
+                "description": "Goes straight for the enemy",
+                "created": "2024-03-01T12:00:00Z",
+            }))
+        });
+        let (address, server) =
+            warp::serve(warp::get().and(robots.or(details))).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+        Api {
+            client: Client::new(),
+            base_url: Url::parse(&format!("http://{}", address)).unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn list_robots() {
+        let api = mock_server();
+        let robots = api.robots("alice").await.unwrap().unwrap();
+        assert_eq!(robots.len(), 2);
+        assert_eq!(robots[0].name, "rush");
+        assert!(robots[0].published);
+        assert_eq!(robots[0].rating, Some(1510.4));
+        assert!(matches!(robots[1].lang, Lang::Javascript));
+        assert!(!robots[1].published);
+        assert_eq!(robots[1].rating, None);
+    }
+
+    #[tokio::test]
+    async fn list_robots_of_missing_user() {
+        let api = mock_server();
+        assert!(api.robots("nobody").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn robot_details() {
+        let api = mock_server();
+        let details = api.robot_details("alice", "rush").await.unwrap().unwrap();
+        assert_eq!(details.summary.id, 1);
+        assert_eq!(details.summary.name, "rush");
+        assert_eq!(
+            details.description.as_deref(),
+            Some("Goes straight for the enemy")
+        );
+        assert_eq!(details.created.as_deref(), Some("2024-03-01T12:00:00Z"));
+        assert_eq!(details.modified, None);
+    }
+
+    #[tokio::test]
+    async fn missing_robot_details() {
+        let api = mock_server();
+        let details = api.robot_details("alice", "nope").await.unwrap();
+        assert!(details.is_none());
+    }
 }
@@ -0,0 +1,308 @@
+use anyhow::Context as _;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use subtle::ConstantTimeEq;
+use tokio::net;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+use warp::http::StatusCode;
+use warp::hyper::body::Bytes;
+use warp::{Filter, Rejection};
+
+use super::{command::CommandLimits, run_game, GameSpec, RobotId, RunOptions};
+
+/// How long a finished job is kept around for if nobody fetches it
+const JOB_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// The largest battle spec that's accepted
+const MAX_BODY: u64 = 1024 * 1024;
+
+pub struct ServeOptions {
+    /// If set, every request must have `Authorization: Bearer $TOKEN`
+    pub token: Option<String>,
+    /// Allow robots that aren't sandboxed, see `RobotId::is_native`
+    pub allow_native: bool,
+    /// Allow robots that are read from files (local robots and ghosts) from under this directory.
+    /// Otherwise they aren't allowed at all, since they could be any file on the server
+    pub robot_root: Option<PathBuf>,
+    /// The most games that can be running at once, counting both `/run` and `/jobs`
+    pub max_games: usize,
+    pub limits: CommandLimits,
+}
+
+#[derive(serde::Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+enum Job {
+    Running,
+    Done { output: logic::MainOutput },
+    Failed { error: String },
+}
+
+#[derive(Clone, Default)]
+struct Jobs {
+    next_id: Arc<AtomicUsize>,
+    jobs: Arc<Mutex<HashMap<usize, Job>>>,
+}
+
+/// A request that was turned down, which `recover` turns into a JSON error response
+#[derive(Debug)]
+struct Refused(StatusCode, String);
+
+impl warp::reject::Reject for Refused {}
+
+fn refuse(status: StatusCode, error: impl Into<String>) -> Rejection {
+    warp::reject::custom(Refused(status, error.into()))
+}
+
+fn error_reply(status: StatusCode, error: &str) -> warp::reply::WithStatus<warp::reply::Json> {
+    warp::reply::with_status(
+        warp::reply::json(&serde_json::json!({ "error": error })),
+        status,
+    )
+}
+
+pub async fn serve(address: String, port: u16, mut opts: ServeOptions) -> anyhow::Result<()> {
+    if let Some(root) = &opts.robot_root {
+        let root = root
+            .canonicalize()
+            .with_context(|| format!("Couldn't find --robot-root {}", root.display()))?;
+        opts.robot_root = Some(root);
+    }
+    let games = Arc::new(Semaphore::new(opts.max_games));
+    let opts = Arc::new(opts);
+    let jobs = Jobs::default();
+    let jobs = warp::any().map(move || jobs.clone());
+    let run_opts = {
+        let opts = opts.clone();
+        warp::any().map(move || opts.clone())
+    };
+    let battle = authorized(opts.clone())
+        .and(battle(opts.clone()))
+        .and(game_slot(games));
+
+    let route = warp::path!("run")
+        .and(warp::post())
+        .and(battle.clone())
+        .and(run_opts.clone())
+        .and_then(run)
+        .or(warp::path!("jobs")
+            .and(warp::post())
+            .and(battle)
+            .and(jobs.clone())
+            .and(run_opts)
+            .map(start_job))
+        .or(warp::path!("jobs" / usize)
+            .and(warp::get())
+            .and(authorized(opts.clone()))
+            .and(jobs)
+            .map(get_job))
+        .recover(recover);
+
+    let addr: std::net::IpAddr = address.parse().context("Invalid address provided")?;
+    let listener = net::TcpListener::bind((addr, port))
+        .await
+        .with_context(|| format!("Couldn't bind on port {}", port))?;
+    eprintln!("API running at http://{}", listener.local_addr()?);
+    if opts.allow_native && opts.token.is_none() {
+        eprintln!(
+            "WARNING: native robots are allowed and no --token is set, so anyone who can reach \
+             the API can run any program as you"
+        );
+    }
+
+    let listener = tokio_stream::wrappers::TcpListenerStream::new(listener);
+    warp::serve(route).run_incoming(listener).await;
+
+    Ok(())
+}
+
+/// Checks the bearer token, if one is required
+fn authorized(opts: Arc<ServeOptions>) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization")
+        .and_then(move |header: Option<String>| {
+            let opts = opts.clone();
+            async move {
+                let token = match &opts.token {
+                    Some(token) => token,
+                    None => return Ok(()),
+                };
+                let given = header.as_deref().and_then(|h| h.strip_prefix("Bearer "));
+                // in constant time, so that the token can't be guessed a byte at a time from how
+                // long it takes to be turned down
+                let matches = given.map_or(false, |given| {
+                    bool::from(given.as_bytes().ct_eq(token.as_bytes()))
+                });
+                if matches {
+                    Ok(())
+                } else {
+                    Err(refuse(
+                        StatusCode::UNAUTHORIZED,
+                        "missing or wrong `Authorization: Bearer` token",
+                    ))
+                }
+            }
+        })
+        .untuple_one()
+}
+
+/// The battle in the request body. Requiring a JSON content type means that a web page can't
+/// send one with a plain cross-origin form post, since browsers would need our permission first
+fn battle(
+    opts: Arc<ServeOptions>,
+) -> impl Filter<Extract = (GameSpec,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("content-type")
+        .and(warp::body::content_length_limit(MAX_BODY))
+        .and(warp::body::bytes())
+        .and_then(move |content_type: Option<String>, body: Bytes| {
+            let opts = opts.clone();
+            async move {
+                let is_json = content_type
+                    .as_deref()
+                    .and_then(|ct| ct.split(';').next())
+                    .map_or(false, |mime| {
+                        mime.trim().eq_ignore_ascii_case("application/json")
+                    });
+                if !is_json {
+                    return Err(refuse(
+                        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                        "the request must have `Content-Type: application/json`",
+                    ));
+                }
+                let spec: GameSpec = serde_json::from_slice(&body).map_err(|err| {
+                    refuse(StatusCode::BAD_REQUEST, format!("invalid battle: {}", err))
+                })?;
+                for id in [&spec.blue, &spec.red] {
+                    let id = RobotId::parse(id.as_ref()).map_err(|err| {
+                        refuse(StatusCode::BAD_REQUEST, format!("invalid robot: {:#}", err))
+                    })?;
+                    if id.is_native() && !opts.allow_native {
+                        return Err(refuse(
+                            StatusCode::FORBIDDEN,
+                            "command:, localrunner:, tcp: and unix: robots aren't allowed; \
+                             start the server with --allow-native to allow them",
+                        ));
+                    }
+                    let file = match &id {
+                        RobotId::Local { source, .. } => Some(source),
+                        RobotId::Ghost { transcript } => Some(transcript),
+                        _ => None,
+                    };
+                    if let Some(file) = file {
+                        check_robot_file(file, opts.robot_root.as_deref())?;
+                    }
+                }
+                Ok(spec)
+            }
+        })
+}
+
+/// Robots from files are only allowed from under `root`, if there is one
+fn check_robot_file(file: &Path, root: Option<&Path>) -> Result<(), Rejection> {
+    let root = root.ok_or_else(|| {
+        refuse(
+            StatusCode::FORBIDDEN,
+            "robots from files aren't allowed; start the server with --robot-root to allow them \
+             from a directory",
+        )
+    })?;
+    // canonicalized, so that neither `..` nor a symlink can get out of the root
+    let inside = file
+        .canonicalize()
+        .map_or(false, |file| file.starts_with(root));
+    if inside {
+        Ok(())
+    } else {
+        Err(refuse(
+            StatusCode::FORBIDDEN,
+            format!("{} isn't a robot under --robot-root", file.display()),
+        ))
+    }
+}
+
+/// A slot to run a game in, which is held until the game is over
+fn game_slot(
+    games: Arc<Semaphore>,
+) -> impl Filter<Extract = (OwnedSemaphorePermit,), Error = Rejection> + Clone {
+    warp::any().and_then(move || {
+        let games = games.clone();
+        async move {
+            games.try_acquire_owned().map_err(|_| {
+                refuse(
+                    StatusCode::TOO_MANY_REQUESTS,
+                    "too many games are running; try again later",
+                )
+            })
+        }
+    })
+}
+
+async fn recover(err: Rejection) -> Result<impl warp::Reply, Rejection> {
+    if let Some(Refused(status, error)) = err.find() {
+        Ok(error_reply(*status, error))
+    } else {
+        Err(err)
+    }
+}
+
+async fn play(spec: GameSpec, opts: &ServeOptions) -> Result<logic::MainOutput, String> {
+    let run_opts = RunOptions {
+        limits: opts.limits.clone(),
+        ..Default::default()
+    };
+    run_game(spec, logic::GameMode::Normal, &run_opts, |_| {})
+        .await
+        .map_err(|err| format!("{:#}", err))
+}
+
+async fn run(
+    spec: GameSpec,
+    _slot: OwnedSemaphorePermit,
+    opts: Arc<ServeOptions>,
+) -> Result<impl warp::Reply, Rejection> {
+    let reply = match play(spec, &opts).await {
+        Ok(output) => warp::reply::with_status(warp::reply::json(&output), StatusCode::OK),
+        Err(error) => error_reply(StatusCode::INTERNAL_SERVER_ERROR, &error),
+    };
+    Ok(reply)
+}
+
+fn start_job(
+    spec: GameSpec,
+    slot: OwnedSemaphorePermit,
+    jobs: Jobs,
+    opts: Arc<ServeOptions>,
+) -> impl warp::Reply {
+    let id = jobs.next_id.fetch_add(1, Ordering::Relaxed);
+    jobs.jobs.lock().unwrap().insert(id, Job::Running);
+    tokio::spawn(async move {
+        let job = match play(spec, &opts).await {
+            Ok(output) => Job::Done { output },
+            Err(error) => Job::Failed { error },
+        };
+        drop(slot);
+        jobs.jobs.lock().unwrap().insert(id, job);
+        tokio::time::sleep(JOB_TTL).await;
+        jobs.jobs.lock().unwrap().remove(&id);
+    });
+    warp::reply::with_status(
+        warp::reply::json(&serde_json::json!({ "id": id })),
+        StatusCode::ACCEPTED,
+    )
+}
+
+/// Finished jobs are removed once they're fetched
+fn get_job(id: usize, jobs: Jobs) -> impl warp::Reply {
+    let mut jobs = jobs.jobs.lock().unwrap();
+    match jobs.get(&id) {
+        Some(Job::Running) => {
+            warp::reply::with_status(warp::reply::json(&Job::Running), StatusCode::OK)
+        }
+        Some(_) => {
+            let job = jobs.remove(&id).unwrap();
+            warp::reply::with_status(warp::reply::json(&job), StatusCode::OK)
+        }
+        None => error_reply(StatusCode::NOT_FOUND, "no such job"),
+    }
+}
@@ -0,0 +1,354 @@
+//! Reference opponents that are built into rumblebot, for `builtin:$NAME`. They run in-process, so
+//! they start up instantly.
+
+use logic::{
+    Action, ActionType, Coords, Direction, GridMap, Obj, ObjDetails, ProgramInput, ProgramOutput,
+    ProgramResult, RobotRunner, Team,
+};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use sha2::{Digest, Sha256};
+
+/// A way of playing the game. Every turn, each of our units is asked what to do
+pub trait Strategy: Send {
+    /// `None` means that the unit does nothing this turn
+    fn act(&mut self, unit: &Obj, game: &Game) -> Option<Action>;
+}
+
+/// All of the built-in strategies, by name. A new strategy just needs to be added here. Strategies
+/// that make random choices must only use the rng they're given, so that games can be replayed
+pub const STRATEGIES: &[(&str, fn(StdRng) -> Box<dyn Strategy>)] = &[
+    ("idle", |_| Box::new(Idle)),
+    ("random", |rng| Box::new(Random(rng))),
+    ("rush", |_| Box::new(Rush)),
+    ("turtle", |_| Box::new(Turtle)),
+];
+
+pub fn names() -> impl Iterator<Item = &'static str> {
+    STRATEGIES.iter().map(|(name, _)| *name)
+}
+
+/// What a strategy can see of the game on a turn
+pub struct Game<'a> {
+    pub team: Team,
+    pub objs: Vec<&'a Obj>,
+    grid: GridMap,
+}
+
+impl Game<'_> {
+    pub fn enemies(&self) -> impl Iterator<Item = &Obj> + '_ {
+        self.objs.iter().copied().filter(
+            move |obj| matches!(obj.details(), ObjDetails::Unit(unit) if unit.team != self.team),
+        )
+    }
+
+    pub fn is_free(&self, coords: Coords) -> bool {
+        self.grid.get(&coords).is_none()
+    }
+
+    /// The direction of an enemy right next to `unit`, if there is one
+    pub fn adjacent_enemy(&self, unit: &Obj) -> Option<Direction> {
+        self.enemies()
+            .filter(|enemy| distance(unit.0.coords, enemy.0.coords) == 1)
+            .find_map(|enemy| direction_to(unit.0.coords, enemy.0.coords))
+    }
+}
+
+fn distance(a: Coords, b: Coords) -> usize {
+    let dx = (a.0 as isize - b.0 as isize).abs();
+    let dy = (a.1 as isize - b.1 as isize).abs();
+    (dx + dy) as usize
+}
+
+fn step(coords: Coords, direction: Direction) -> Option<Coords> {
+    let Coords(x, y) = coords;
+    Some(match direction {
+        Direction::North => Coords(x, y.checked_sub(1)?),
+        Direction::South => Coords(x, y + 1),
+        Direction::East => Coords(x + 1, y),
+        Direction::West => Coords(x.checked_sub(1)?, y),
+    })
+}
+
+/// The directions that get closer to `to`, the one that closes the longer distance first
+fn directions_to(from: Coords, to: Coords) -> Vec<Direction> {
+    let horizontal = match to.0.cmp(&from.0) {
+        std::cmp::Ordering::Greater => Some(Direction::East),
+        std::cmp::Ordering::Less => Some(Direction::West),
+        std::cmp::Ordering::Equal => None,
+    };
+    let vertical = match to.1.cmp(&from.1) {
+        std::cmp::Ordering::Greater => Some(Direction::South),
+        std::cmp::Ordering::Less => Some(Direction::North),
+        std::cmp::Ordering::Equal => None,
+    };
+    let dx = (to.0 as isize - from.0 as isize).abs();
+    let dy = (to.1 as isize - from.1 as isize).abs();
+    let (first, second) = if dx >= dy {
+        (horizontal, vertical)
+    } else {
+        (vertical, horizontal)
+    };
+    first.into_iter().chain(second).collect()
+}
+
+fn direction_to(from: Coords, to: Coords) -> Option<Direction> {
+    directions_to(from, to).into_iter().next()
+}
+
+const DIRECTIONS: [Direction; 4] = [
+    Direction::North,
+    Direction::South,
+    Direction::East,
+    Direction::West,
+];
+
+fn attack(direction: Direction) -> Action {
+    Action {
+        type_: ActionType::Attack,
+        direction,
+    }
+}
+
+fn move_(direction: Direction) -> Action {
+    Action {
+        type_: ActionType::Move,
+        direction,
+    }
+}
+
+struct Idle;
+
+impl Strategy for Idle {
+    fn act(&mut self, _unit: &Obj, _game: &Game) -> Option<Action> {
+        None
+    }
+}
+
+struct Random(StdRng);
+
+impl Strategy for Random {
+    fn act(&mut self, _unit: &Obj, _game: &Game) -> Option<Action> {
+        let direction = *DIRECTIONS.choose(&mut self.0).unwrap();
+        Some(if self.0.gen_bool(0.5) {
+            move_(direction)
+        } else {
+            attack(direction)
+        })
+    }
+}
+
+struct Rush;
+
+impl Strategy for Rush {
+    fn act(&mut self, unit: &Obj, game: &Game) -> Option<Action> {
+        if let Some(direction) = game.adjacent_enemy(unit) {
+            return Some(attack(direction));
+        }
+        let coords = unit.0.coords;
+        let target = game
+            .enemies()
+            .min_by_key(|enemy| distance(coords, enemy.0.coords))?;
+        directions_to(coords, target.0.coords)
+            .into_iter()
+            .find(|&direction| step(coords, direction).map_or(false, |c| game.is_free(c)))
+            .map(move_)
+    }
+}
+
+struct Turtle;
+
+impl Strategy for Turtle {
+    fn act(&mut self, unit: &Obj, game: &Game) -> Option<Action> {
+        game.adjacent_enemy(unit).map(attack)
+    }
+}
+
+/// Runs a `Strategy` for every unit on its team
+pub struct Builtin {
+    strategy: Box<dyn Strategy>,
+}
+
+impl Builtin {
+    /// `seed` is what the strategy's random choices are derived from
+    pub fn new(name: &str, seed: &str) -> Option<Self> {
+        let rng = StdRng::from_seed(Sha256::digest(seed.as_bytes()).into());
+        STRATEGIES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|(_, make)| Self {
+                strategy: make(rng),
+            })
+    }
+}
+
+#[async_trait::async_trait]
+impl RobotRunner for Builtin {
+    async fn run(&mut self, input: ProgramInput<'_>) -> ProgramResult {
+        let game = Game {
+            team: input.team,
+            objs: input.state.objs.values().collect(),
+            grid: GridMap::from(&input.state.objs),
+        };
+        let robot_actions = game
+            .objs
+            .iter()
+            .filter(|obj| matches!(obj.details(), ObjDetails::Unit(unit) if unit.team == game.team))
+            .map(|unit| (unit.0.id, Ok(self.strategy.act(unit, &game))))
+            .collect();
+        Ok(ProgramOutput {
+            robot_actions,
+            ..Default::default()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{directions_to, step, Game, Rush, Strategy, Turtle};
+    use logic::{
+        Action, ActionType, BasicObj, Coords, Direction, GridMap, Id, Obj, ObjDetails, ObjMap,
+        Team, Unit, UnitType,
+    };
+
+    fn unit(id: usize, team: Team, x: usize, y: usize) -> Obj {
+        Obj(
+            BasicObj {
+                id: Id(id),
+                coords: Coords(x, y),
+            },
+            ObjDetails::Unit(Unit {
+                type_: UnitType::Soldier,
+                team,
+                health: 5,
+            }),
+        )
+    }
+
+    /// Asks `strategy` what the first unit does, with the rest of the units on the board
+    fn act(strategy: &mut dyn Strategy, units: Vec<Obj>) -> Option<Action> {
+        let me = units[0].clone();
+        let objs: ObjMap = units.into_iter().map(|obj| (obj.0.id, obj)).collect();
+        let game = Game {
+            team: Team::Blue,
+            objs: objs.values().collect(),
+            grid: GridMap::from(&objs),
+        };
+        strategy.act(&me, &game)
+    }
+
+    #[test]
+    fn step_stays_on_the_board() {
+        let coords = |c: Option<Coords>| c.map(|Coords(x, y)| (x, y));
+        assert_eq!(coords(step(Coords(0, 0), Direction::North)), None);
+        assert_eq!(coords(step(Coords(0, 0), Direction::West)), None);
+        assert_eq!(coords(step(Coords(0, 0), Direction::South)), Some((0, 1)));
+        assert_eq!(coords(step(Coords(0, 0), Direction::East)), Some((1, 0)));
+        assert_eq!(coords(step(Coords(3, 3), Direction::North)), Some((3, 2)));
+        assert_eq!(coords(step(Coords(3, 3), Direction::West)), Some((2, 3)));
+    }
+
+    #[test]
+    fn directions_to_closes_the_longer_distance_first() {
+        assert!(matches!(
+            directions_to(Coords(1, 1), Coords(5, 2)).as_slice(),
+            [Direction::East, Direction::South]
+        ));
+        assert!(matches!(
+            directions_to(Coords(5, 5), Coords(4, 1)).as_slice(),
+            [Direction::North, Direction::West]
+        ));
+        assert!(matches!(
+            directions_to(Coords(2, 2), Coords(2, 4)).as_slice(),
+            [Direction::South]
+        ));
+        assert!(directions_to(Coords(2, 2), Coords(2, 2)).is_empty());
+    }
+
+    #[test]
+    fn turtle_only_attacks_adjacent_enemies() {
+        let action = act(
+            &mut Turtle,
+            vec![unit(1, Team::Blue, 3, 3), unit(2, Team::Red, 4, 3)],
+        );
+        assert!(matches!(
+            action,
+            Some(Action {
+                type_: ActionType::Attack,
+                direction: Direction::East,
+            })
+        ));
+        let action = act(
+            &mut Turtle,
+            vec![unit(1, Team::Blue, 3, 3), unit(2, Team::Red, 5, 3)],
+        );
+        assert!(action.is_none());
+        // not an enemy
+        let action = act(
+            &mut Turtle,
+            vec![unit(1, Team::Blue, 3, 3), unit(2, Team::Blue, 4, 3)],
+        );
+        assert!(action.is_none());
+    }
+
+    #[test]
+    fn rush_attacks_or_moves_towards_the_nearest_enemy() {
+        let action = act(
+            &mut Rush,
+            vec![unit(1, Team::Blue, 3, 3), unit(2, Team::Red, 3, 2)],
+        );
+        assert!(matches!(
+            action,
+            Some(Action {
+                type_: ActionType::Attack,
+                direction: Direction::North,
+            })
+        ));
+        let action = act(
+            &mut Rush,
+            vec![
+                unit(1, Team::Blue, 3, 3),
+                unit(2, Team::Red, 8, 3),
+                unit(3, Team::Red, 3, 6),
+            ],
+        );
+        assert!(matches!(
+            action,
+            Some(Action {
+                type_: ActionType::Move,
+                direction: Direction::South,
+            })
+        ));
+    }
+
+    #[test]
+    fn rush_goes_around_blocked_cells() {
+        // the direct way east is taken by a teammate, so it goes south instead
+        let action = act(
+            &mut Rush,
+            vec![
+                unit(1, Team::Blue, 3, 3),
+                unit(2, Team::Blue, 4, 3),
+                unit(3, Team::Red, 7, 5),
+            ],
+        );
+        assert!(matches!(
+            action,
+            Some(Action {
+                type_: ActionType::Move,
+                direction: Direction::South,
+            })
+        ));
+        // no way closer at all
+        let action = act(
+            &mut Rush,
+            vec![
+                unit(1, Team::Blue, 3, 3),
+                unit(2, Team::Blue, 4, 3),
+                unit(3, Team::Red, 7, 3),
+            ],
+        );
+        assert!(action.is_none());
+    }
+}
@@ -0,0 +1,918 @@
+//! Bundling a robot that's split across several files into a single file, since that's all that
+//! robotrumble.org accepts. Only local modules are bundled; anything else is imported as usual.
+
+use anyhow::{anyhow, bail, Context};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use super::{manifest, Lang};
+
+/// Bundle a robot file or directory, along with all of the local modules it imports
+pub fn bundle(source: &Path, lang: Lang) -> anyhow::Result<String> {
+    let entry = manifest::entry_file(source)?;
+    let root = if source.is_dir() {
+        source.to_owned()
+    } else {
+        entry.parent().unwrap_or_else(|| Path::new(".")).to_owned()
+    };
+    let code = match lang {
+        Lang::Python => bundle_python(&entry),
+        Lang::Javascript => bundle_javascript(&root, &entry),
+    };
+    code.with_context(|| format!("Couldn't bundle {}", source.display()))
+}
+
+fn read(path: &Path) -> anyhow::Result<String> {
+    fs::read_to_string(path).with_context(|| format!("Couldn't read {}", path.display()))
+}
+
+/// A string literal that's valid in both Python and JavaScript
+fn string_literal(s: &str) -> String {
+    serde_json::to_string(s).unwrap()
+}
+
+struct PyModule {
+    is_package: bool,
+    /// The path relative to the entry's directory, for tracebacks
+    path: String,
+    source: String,
+}
+
+/// Local Python modules are put in a dict and loaded by an import hook, so they're only run when
+/// they're imported, just like they would be normally
+fn bundle_python(entry: &Path) -> anyhow::Result<String> {
+    // the entry's directory is what's on `sys.path`
+    let root = entry.parent().unwrap_or_else(|| Path::new("."));
+    let entry_source = read(entry)?;
+
+    let mut modules = BTreeMap::<String, PyModule>::new();
+    let mut queue = vec![(None, entry_source.clone())];
+    while let Some((name, source)) = queue.pop() {
+        let current = name.as_ref().map(|name: &String| {
+            let is_package = modules[name].is_package;
+            (name.as_str(), is_package)
+        });
+        for import in python_imports(&source) {
+            let module = match import.resolve(current) {
+                Some(module) => module,
+                None => continue,
+            };
+            let mut candidates = vec![module.clone()];
+            candidates.extend(import.names.iter().map(|n| format!("{}.{}", module, n)));
+            for candidate in candidates {
+                for name in module_prefixes(&candidate) {
+                    if modules.contains_key(&name) {
+                        continue;
+                    }
+                    if let Some(module) = find_py_module(root, &name)? {
+                        queue.push((Some(name.clone()), module.source.clone()));
+                        modules.insert(name, module);
+                    }
+                }
+            }
+        }
+    }
+
+    let (future_imports, entry_source) = split_future_imports(&entry_source);
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "# Bundled by rumblebot from {} and {} local module(s)",
+        entry.file_name().unwrap_or_default().to_string_lossy(),
+        modules.len()
+    );
+    out.push_str(&future_imports);
+    if !modules.is_empty() {
+        out.push_str("_rumblebot_modules = {\n");
+        for (name, module) in &modules {
+            let _ = writeln!(
+                out,
+                "    {}: ({}, {}, {}),",
+                string_literal(name),
+                if module.is_package { "True" } else { "False" },
+                string_literal(&module.path),
+                string_literal(&module.source)
+            );
+        }
+        out.push_str("}\n");
+        out.push_str(PYTHON_IMPORTER);
+    }
+    out.push('\n');
+    out.push_str(&entry_source);
+    Ok(out)
+}
+
+/// `from __future__` imports have to come before any other code, so they're taken out of the entry
+/// file to go at the top of the bundle. Returns them, and the rest of the entry file
+fn split_future_imports(source: &str) -> (String, String) {
+    let mut future = String::new();
+    let mut rest = String::new();
+    let mut lines = source.lines();
+    while let Some(line) = lines.next() {
+        if !line.starts_with("from __future__ ") {
+            rest.push_str(line);
+            rest.push('\n');
+            continue;
+        }
+        future.push_str(line);
+        future.push('\n');
+        if line.contains('(') && !line.contains(')') {
+            for line in lines.by_ref() {
+                future.push_str(line);
+                future.push('\n');
+                if line.contains(')') {
+                    break;
+                }
+            }
+        }
+    }
+    (future, rest)
+}
+
+const PYTHON_IMPORTER: &str = r#"
+
+class _RumblebotImporter:
+    @classmethod
+    def find_spec(cls, name, path=None, target=None):
+        if name not in _rumblebot_modules:
+            return None
+        import importlib.util
+        is_package = _rumblebot_modules[name][0]
+        return importlib.util.spec_from_loader(name, cls, is_package=is_package)
+
+    @staticmethod
+    def create_module(spec):
+        return None
+
+    @staticmethod
+    def exec_module(module):
+        _, path, source = _rumblebot_modules[module.__name__]
+        exec(compile(source, path, "exec"), module.__dict__)
+
+
+import sys as _rumblebot_sys
+_rumblebot_sys.meta_path.insert(0, _RumblebotImporter)
+"#;
+
+struct PyImport {
+    /// The number of leading dots, for relative imports
+    level: usize,
+    module: String,
+    /// The names in a `from ... import ...`, which might be submodules
+    names: Vec<String>,
+}
+
+impl PyImport {
+    /// The absolute name of the imported module. `current` is the module that's importing it,
+    /// and whether it's a package
+    fn resolve(&self, current: Option<(&str, bool)>) -> Option<String> {
+        if self.level == 0 {
+            return Some(self.module.clone());
+        }
+        // relative imports don't work in the entry file, since it's `__main__`
+        let (current, is_package) = current?;
+        let mut package: Vec<&str> = current.split('.').collect();
+        if !is_package {
+            package.pop();
+        }
+        for _ in 1..self.level {
+            package.pop()?;
+        }
+        // beyond the top-level package
+        if package.is_empty() {
+            return None;
+        }
+        if !self.module.is_empty() {
+            package.push(&self.module);
+        }
+        Some(package.join("."))
+    }
+}
+
+fn python_imports(source: &str) -> Vec<PyImport> {
+    let mut imports = Vec::new();
+    let mut lines = source.lines();
+    while let Some(line) = lines.next() {
+        let line = line.trim();
+        if let Some(rest) = line.strip_prefix("import ") {
+            for part in rest.split(',') {
+                let module = part.split_whitespace().next().unwrap_or("");
+                if !module.is_empty() {
+                    imports.push(PyImport {
+                        level: 0,
+                        module: module.to_owned(),
+                        names: Vec::new(),
+                    });
+                }
+            }
+        } else if let Some(rest) = line.strip_prefix("from ") {
+            let (module, names) = match rest.split_once(" import ") {
+                Some(x) => x,
+                None => continue,
+            };
+            let module = module.trim();
+            let mut names = names.to_owned();
+            // `from x import (a,\n b)` can span multiple lines
+            if names.trim_start().starts_with('(') && !names.contains(')') {
+                for line in lines.by_ref() {
+                    names.push(' ');
+                    names.push_str(line);
+                    if line.contains(')') {
+                        break;
+                    }
+                }
+            }
+            let names = names
+                .split([',', '(', ')'])
+                .filter_map(|name| name.split_whitespace().next())
+                .filter(|name| !name.starts_with('#') && *name != "*")
+                .map(str::to_owned)
+                .collect();
+            let level = module.len() - module.trim_start_matches('.').len();
+            imports.push(PyImport {
+                level,
+                module: module[level..].to_owned(),
+                names,
+            });
+        }
+    }
+    imports
+}
+
+/// `a.b.c` -> `a`, `a.b`, `a.b.c`, since importing a submodule imports its parents too
+fn module_prefixes(name: &str) -> Vec<String> {
+    let parts: Vec<&str> = name.split('.').collect();
+    (1..=parts.len()).map(|n| parts[..n].join(".")).collect()
+}
+
+fn find_py_module(root: &Path, name: &str) -> anyhow::Result<Option<PyModule>> {
+    let valid = name
+        .split('.')
+        .all(|part| !part.is_empty() && part.chars().all(|c| c.is_alphanumeric() || c == '_'));
+    if !valid {
+        return Ok(None);
+    }
+    let base = name.replace('.', "/");
+    for (is_package, path) in [
+        (false, format!("{}.py", base)),
+        (true, format!("{}/__init__.py", base)),
+    ] {
+        let file = root.join(&path);
+        if file.is_file() {
+            let source = read(&file)?;
+            return Ok(Some(PyModule {
+                is_package,
+                path,
+                source,
+            }));
+        }
+    }
+    Ok(None)
+}
+
+/// Local JavaScript modules are wrapped in functions, like in CommonJS, and all the local
+/// `require()`s and `import`s are rewritten to load them. The entry file is left at the top level,
+/// so that the `robot` function is still a global
+fn bundle_javascript(root: &Path, entry: &Path) -> anyhow::Result<String> {
+    let entry_key = js_module_key(root, entry)?;
+    let mut modules = BTreeMap::new();
+    let mut queue = vec![entry.to_owned()];
+    let mut seen = BTreeSet::new();
+    seen.insert(entry_key.clone());
+    while let Some(path) = queue.pop() {
+        let key = js_module_key(root, &path)?;
+        let source = read(&path)?;
+        let dir = path.parent().unwrap_or(root);
+        let resolve = |spec: &str| -> anyhow::Result<Option<String>> {
+            if !spec.starts_with("./") && !spec.starts_with("../") {
+                return Ok(None);
+            }
+            let target = find_js_module(&dir.join(spec))
+                .ok_or_else(|| anyhow!("Couldn't find module {:?} imported from {}", spec, key))?;
+            js_module_key(root, &target).map(Some)
+        };
+        let code = transform_javascript(&source, key == entry_key, &resolve)
+            .with_context(|| format!("Couldn't bundle {}", key))?;
+        for spec in js_specifiers(&source) {
+            if let Some(dep) = resolve(&spec)? {
+                if seen.insert(dep.clone()) {
+                    queue.push(root.join(&dep));
+                }
+            }
+        }
+        modules.insert(key, code);
+    }
+
+    let entry_code = modules.remove(&entry_key).unwrap();
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "// Bundled by rumblebot from {} and {} local module(s)",
+        entry_key,
+        modules.len()
+    );
+    out.push_str("const __rumblebot_modules = {\n");
+    for (key, code) in &modules {
+        let _ = writeln!(
+            out,
+            "{}: function (module, exports) {{\n{}\n}},",
+            string_literal(key),
+            code
+        );
+    }
+    out.push_str("};\n");
+    out.push_str(JAVASCRIPT_LOADER);
+    out.push('\n');
+    out.push_str(&entry_code);
+    Ok(out)
+}
+
+const JAVASCRIPT_LOADER: &str = r#"const __rumblebot_cache = {};
+function __rumblebot_load(name) {
+  if (!(name in __rumblebot_cache)) {
+    const module = { exports: {} };
+    __rumblebot_cache[name] = module;
+    __rumblebot_modules[name](module, module.exports);
+  }
+  return __rumblebot_cache[name].exports;
+}
+function __rumblebot_default(m) {
+  return m && Object.prototype.hasOwnProperty.call(m, "default") ? m.default : m;
+}
+"#;
+
+/// The module's path relative to the robot directory, with `/` separators
+fn js_module_key(root: &Path, path: &Path) -> anyhow::Result<String> {
+    let mut parts: Vec<String> = Vec::new();
+    let rel = path.strip_prefix(root).unwrap_or(path);
+    for component in rel.components() {
+        match component {
+            Component::Normal(s) => parts.push(s.to_string_lossy().into_owned()),
+            Component::CurDir => {}
+            Component::ParentDir if parts.pop().is_some() => {}
+            _ => bail!("{} is outside of the robot directory", path.display()),
+        }
+    }
+    Ok(parts.join("/"))
+}
+
+fn find_js_module(base: &Path) -> Option<PathBuf> {
+    let base = base.to_string_lossy();
+    let candidates = [
+        base.to_string(),
+        format!("{}.js", base),
+        format!("{}.mjs", base),
+        format!("{}/index.js", base),
+    ];
+    candidates
+        .iter()
+        .map(PathBuf::from)
+        .find(|path| path.is_file())
+}
+
+/// Every string passed to `require()` or imported from
+fn js_specifiers(source: &str) -> Vec<String> {
+    let mut specs = Vec::new();
+    let mut rest = source;
+    while let Some((_, spec, after)) = find_require(rest) {
+        specs.push(spec.to_owned());
+        rest = after;
+    }
+    for statement in js_statements(source) {
+        if let Some(spec) = statement.import_from().or_else(|| statement.export_from()) {
+            specs.push(spec.to_owned());
+        }
+    }
+    specs
+}
+
+/// Find the next `require("...")`, returning the code before it, the string, and the code after it
+fn find_require(source: &str) -> Option<(&str, &str, &str)> {
+    let mut offset = 0;
+    loop {
+        let start = offset + source[offset..].find("require")?;
+        offset = start + "require".len();
+        let preceded_by_ident = source[..start].chars().next_back().map_or(false, |c| {
+            c.is_alphanumeric() || c == '_' || c == '$' || c == '.'
+        });
+        if preceded_by_ident {
+            continue;
+        }
+        let after = source[offset..].trim_start();
+        let after = match after.strip_prefix('(') {
+            Some(after) => after.trim_start(),
+            None => continue,
+        };
+        let (spec, after) = match string_prefix(after) {
+            Some(x) => x,
+            None => continue,
+        };
+        let after = match after.trim_start().strip_prefix(')') {
+            Some(after) => after,
+            None => continue,
+        };
+        return Some((&source[..start], spec, after));
+    }
+}
+
+/// If `s` starts with a simple string literal, return its contents and the code after it
+fn string_prefix(s: &str) -> Option<(&str, &str)> {
+    let quote = s.chars().next().filter(|&c| c == '"' || c == '\'')?;
+    let end = s[1..].find(quote)? + 1;
+    let contents = &s[1..end];
+    if contents.contains('\\') || contents.contains('\n') {
+        return None;
+    }
+    Some((contents, &s[end + 1..]))
+}
+
+/// A line of code, or a multi-line `import { ... } from "..."` or `export { ... }`
+struct JsStatement<'a> {
+    indent: &'a str,
+    text: String,
+}
+
+impl JsStatement<'_> {
+    fn import_from(&self) -> Option<&str> {
+        let text = self.text.strip_prefix("import")?;
+        if !text.starts_with(|c: char| {
+            c.is_whitespace() || c == '{' || c == '*' || c == '"' || c == '\''
+        }) {
+            return None;
+        }
+        if let Some((spec, _)) = string_prefix(text.trim_start()) {
+            return Some(spec);
+        }
+        split_from(text).map(|(_, spec)| spec)
+    }
+
+    /// The module re-exported from by `export { ... } from "..."` or `export * from "..."`
+    fn export_from(&self) -> Option<&str> {
+        let text = self.text.strip_prefix("export")?.trim_start();
+        if !text.starts_with('{') && !text.starts_with('*') {
+            return None;
+        }
+        split_from(text).map(|(_, spec)| spec)
+    }
+}
+
+/// Whether the line starts an `import { ...` or `export { ...` that continues on the next lines
+fn opens_binding_list(text: &str) -> bool {
+    let rest = match text
+        .strip_prefix("import")
+        .or_else(|| text.strip_prefix("export"))
+    {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let rest = rest.trim_start();
+    let is_list = if text.starts_with("import") {
+        // not a dynamic `import(...)`
+        !rest.starts_with('(') && rest.contains('{')
+    } else {
+        rest.starts_with('{')
+    };
+    is_list && !rest.contains('}')
+}
+
+/// Split `{ a, b } from "./x"` into the bindings and the module
+fn split_from(text: &str) -> Option<(&str, &str)> {
+    text.rmatch_indices("from").find_map(|(i, _)| {
+        let (spec, _) = string_prefix(text[i + "from".len()..].trim_start())?;
+        Some((text[..i].trim(), spec))
+    })
+}
+
+fn js_statements(source: &str) -> Vec<JsStatement<'_>> {
+    let mut statements = Vec::new();
+    let mut lines = source.lines();
+    while let Some(line) = lines.next() {
+        let text = line.trim_start();
+        let indent = &line[..line.len() - text.len()];
+        let mut text = text.to_owned();
+        if opens_binding_list(&text) {
+            for line in lines.by_ref() {
+                text.push(' ');
+                text.push_str(line.trim());
+                if line.contains('}') {
+                    break;
+                }
+            }
+        }
+        statements.push(JsStatement { indent, text });
+    }
+    statements
+}
+
+/// Turn ES module syntax into CommonJS, and point local `require()`s at the bundled modules
+fn transform_javascript(
+    source: &str,
+    is_entry: bool,
+    resolve: &dyn Fn(&str) -> anyhow::Result<Option<String>>,
+) -> anyhow::Result<String> {
+    let load = |spec: &str| -> anyhow::Result<String> {
+        Ok(match resolve(spec)? {
+            Some(key) => format!("__rumblebot_load({})", string_literal(&key)),
+            None => format!("require({})", string_literal(spec)),
+        })
+    };
+
+    let mut out = String::new();
+    let mut exports = Vec::new();
+    for (i, statement) in js_statements(source).into_iter().enumerate() {
+        let text = rewrite_requires(&statement.text, &load)?;
+        let text = if let Some(spec) = statement.import_from() {
+            rewrite_import(&text, &load(spec)?, i)?
+        } else if let Some(spec) = statement.export_from() {
+            rewrite_reexport(&text, &load(spec)?, i, &mut exports)?
+        } else {
+            rewrite_export(&text, is_entry, &mut exports)?
+        };
+        out.push_str(statement.indent);
+        out.push_str(&text);
+        out.push('\n');
+    }
+    // nothing imports the entry file, so its exports can be dropped. Named exports go last, since
+    // they take precedence over `export *`
+    if !is_entry {
+        for export in &exports {
+            if let Export::All(module) = export {
+                let _ = writeln!(
+                    out,
+                    "for (const k in {0}) if (k !== \"default\") exports[k] = {0}[k];",
+                    module
+                );
+            }
+        }
+        for export in &exports {
+            if let Export::Named(exported, value) = export {
+                let _ = writeln!(out, "exports.{} = {};", exported, value);
+            }
+        }
+    }
+    Ok(out)
+}
+
+/// Something a module exports, which is assigned to `exports` at the end of the module
+enum Export {
+    /// The exported name, and the expression for its value
+    Named(String, String),
+    /// Everything but the default export of the module in the variable, from `export * from`
+    All(String),
+}
+
+fn rewrite_requires(
+    text: &str,
+    load: &dyn Fn(&str) -> anyhow::Result<String>,
+) -> anyhow::Result<String> {
+    let mut out = String::new();
+    let mut rest = text;
+    while let Some((before, spec, after)) = find_require(rest) {
+        out.push_str(before);
+        out.push_str(&load(spec)?);
+        rest = after;
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+fn rewrite_import(text: &str, load: &str, i: usize) -> anyhow::Result<String> {
+    let text = text.trim_end().trim_end_matches(';');
+    let bindings = text["import".len()..].trim();
+    // `import "./x"`
+    if bindings.starts_with(['"', '\'']) {
+        return Ok(format!("{};", load));
+    }
+    let bindings = match split_from(bindings) {
+        Some((bindings, _)) => bindings,
+        None => bail!("unsupported import: {}", text),
+    };
+    let (default, rest) = match bindings.split_once(',') {
+        Some((default, rest)) if !default.contains('{') && !default.contains('*') => {
+            (Some(default.trim()), Some(rest.trim()))
+        }
+        _ if bindings.starts_with('{') || bindings.starts_with('*') => (None, Some(bindings)),
+        _ => (Some(bindings), None),
+    };
+    let module = format!("__rumblebot_import_{}", i);
+    let mut out = format!("const {} = {};", module, load);
+    if let Some(default) = default {
+        let _ = write!(out, " const {} = __rumblebot_default({});", default, module);
+    }
+    match rest {
+        Some(rest) if rest.starts_with('*') => {
+            let name = rest
+                .trim_start_matches('*')
+                .trim()
+                .strip_prefix("as")
+                .map(str::trim)
+                .ok_or_else(|| anyhow!("unsupported import: {}", text))?;
+            let _ = write!(out, " const {} = {};", name, module);
+        }
+        Some(rest) if rest.starts_with('{') => {
+            let names = rest.trim_start_matches('{').trim_end_matches('}');
+            let names = names
+                .split(',')
+                .map(str::trim)
+                .filter(|name| !name.is_empty())
+                .map(|name| match name.split_once(" as ") {
+                    Some((name, alias)) => format!("{}: {}", name.trim(), alias.trim()),
+                    None => name.to_owned(),
+                })
+                .collect::<Vec<_>>()
+                .join(", ");
+            let _ = write!(out, " const {{ {} }} = {};", names, module);
+        }
+        Some(_) => bail!("unsupported import: {}", text),
+        None => {}
+    }
+    Ok(out)
+}
+
+/// Load the module for `export ... from "..."`, collecting what's re-exported from it
+fn rewrite_reexport(
+    text: &str,
+    load: &str,
+    i: usize,
+    exports: &mut Vec<Export>,
+) -> anyhow::Result<String> {
+    let text = text.trim_end().trim_end_matches(';');
+    let bindings = match split_from(text["export".len()..].trim()) {
+        Some((bindings, _)) => bindings,
+        None => bail!("unsupported export: {}", text),
+    };
+    let module = format!("__rumblebot_import_{}", i);
+    if let Some(rest) = bindings.strip_prefix('*') {
+        let rest = rest.trim();
+        if rest.is_empty() {
+            exports.push(Export::All(module.clone()));
+        } else if let Some(name) = rest.strip_prefix("as ") {
+            exports.push(Export::Named(name.trim().to_owned(), module.clone()));
+        } else {
+            bail!("unsupported export: {}", text);
+        }
+    } else if let Some(list) = bindings.strip_prefix('{').and_then(|l| l.strip_suffix('}')) {
+        for name in list.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            let (local, exported) = name.split_once(" as ").unwrap_or((name, name));
+            exports.push(Export::Named(
+                exported.trim().to_owned(),
+                format!("{}.{}", module, local.trim()),
+            ));
+        }
+    } else {
+        bail!("unsupported export: {}", text);
+    }
+    Ok(format!("const {} = {};", module, load))
+}
+
+/// Turn `export` declarations into normal ones, collecting what they export
+fn rewrite_export(text: &str, is_entry: bool, exports: &mut Vec<Export>) -> anyhow::Result<String> {
+    let rest = match text.strip_prefix("export ") {
+        Some(rest) => rest.trim_start(),
+        None => return Ok(text.to_owned()),
+    };
+    let ident = |s: &str| -> String {
+        s.chars()
+            .take_while(|&c| c.is_alphanumeric() || c == '_' || c == '$')
+            .collect()
+    };
+    if let Some(value) = rest.strip_prefix("default ") {
+        let value = value.trim_start();
+        for keyword in &["function ", "function* ", "async function ", "class "] {
+            if let Some(name) = value.strip_prefix(keyword).map(|s| ident(s.trim_start())) {
+                if !name.is_empty() {
+                    exports.push(Export::Named("default".to_owned(), name));
+                    return Ok(value.to_owned());
+                }
+            }
+        }
+        return Ok(if is_entry {
+            value.to_owned()
+        } else {
+            format!("exports.default = {}", value)
+        });
+    }
+    if let Some(list) = rest.strip_prefix('{') {
+        let list = list.split('}').next().unwrap_or("");
+        for name in list.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            let (local, exported) = name.split_once(" as ").unwrap_or((name, name));
+            exports.push(Export::Named(
+                exported.trim().to_owned(),
+                local.trim().to_owned(),
+            ));
+        }
+        return Ok(String::new());
+    }
+    if rest.starts_with('*') {
+        bail!("unsupported export: {}", text);
+    }
+    for keyword in &[
+        "function ",
+        "function* ",
+        "async function ",
+        "class ",
+        "const ",
+        "let ",
+        "var ",
+    ] {
+        if let Some(decl) = rest.strip_prefix(keyword) {
+            let name = ident(decl.trim_start());
+            if !name.is_empty() {
+                exports.push(Export::Named(name.clone(), name));
+            }
+            break;
+        }
+    }
+    Ok(rest.to_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Write `files` into a temporary robot directory, and bundle it
+    fn bundle_files(lang: Lang, files: &[(&str, &str)]) -> String {
+        let dir = tempfile::tempdir().unwrap();
+        for (path, source) in files {
+            let path = dir.path().join(path);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, source).unwrap();
+        }
+        bundle(dir.path(), lang).unwrap()
+    }
+
+    /// Transform a module that isn't the entry, where `./x` and `./y` are local modules
+    fn transform(source: &str) -> anyhow::Result<String> {
+        let resolve = |spec: &str| -> anyhow::Result<Option<String>> {
+            Ok(match spec {
+                "./x" => Some("x.js".to_owned()),
+                "./y" => Some("y.js".to_owned()),
+                _ => None,
+            })
+        };
+        transform_javascript(source, false, &resolve)
+    }
+
+    #[test]
+    fn js_named_imports() {
+        assert_eq!(
+            transform("import { a, b as c } from \"./x\";").unwrap(),
+            "const __rumblebot_import_0 = __rumblebot_load(\"x.js\"); \
+             const { a, b: c } = __rumblebot_import_0;\n"
+        );
+    }
+
+    #[test]
+    fn js_default_imports() {
+        assert_eq!(
+            transform("import d, { a } from './x'").unwrap(),
+            "const __rumblebot_import_0 = __rumblebot_load(\"x.js\"); \
+             const d = __rumblebot_default(__rumblebot_import_0); \
+             const { a } = __rumblebot_import_0;\n"
+        );
+    }
+
+    #[test]
+    fn js_namespace_imports() {
+        assert_eq!(
+            transform("import * as ns from \"./x\";").unwrap(),
+            "const __rumblebot_import_0 = __rumblebot_load(\"x.js\"); \
+             const ns = __rumblebot_import_0;\n"
+        );
+    }
+
+    #[test]
+    fn js_multiline_imports() {
+        let source = "import {\n  a,\n  b,\n} from \"./x\";\nfoo(a, b);";
+        assert_eq!(
+            transform(source).unwrap(),
+            "const __rumblebot_import_0 = __rumblebot_load(\"x.js\"); \
+             const { a, b } = __rumblebot_import_0;\nfoo(a, b);\n"
+        );
+    }
+
+    #[test]
+    fn js_other_imports_are_required() {
+        assert_eq!(
+            transform("import fs from \"fs\";\nconst y = require(\"./y\");").unwrap(),
+            "const __rumblebot_import_0 = require(\"fs\"); \
+             const fs = __rumblebot_default(__rumblebot_import_0);\n\
+             const y = __rumblebot_load(\"y.js\");\n"
+        );
+    }
+
+    #[test]
+    fn js_exports() {
+        let source = "export function f() {\n  return 1;\n}\nconst g = 2;\nexport {\n  g as h,\n};";
+        assert_eq!(
+            transform(source).unwrap(),
+            "function f() {\n  return 1;\n}\nconst g = 2;\n\nexports.f = f;\nexports.h = g;\n"
+        );
+    }
+
+    #[test]
+    fn js_reexports() {
+        let source = "export { a as b, c } from \"./x\";\nexport * from \"./y\";\n\
+                      export * as z from \"./x\";";
+        assert_eq!(
+            transform(source).unwrap(),
+            "const __rumblebot_import_0 = __rumblebot_load(\"x.js\");\n\
+             const __rumblebot_import_1 = __rumblebot_load(\"y.js\");\n\
+             const __rumblebot_import_2 = __rumblebot_load(\"x.js\");\n\
+             for (const k in __rumblebot_import_1) \
+             if (k !== \"default\") exports[k] = __rumblebot_import_1[k];\n\
+             exports.b = __rumblebot_import_0.a;\n\
+             exports.c = __rumblebot_import_0.c;\n\
+             exports.z = __rumblebot_import_2;\n"
+        );
+    }
+
+    #[test]
+    fn js_unsupported_reexport() {
+        assert!(transform("export * frm \"./x\";").is_err());
+        assert!(transform("export * as from \"./x\";").is_err());
+    }
+
+    #[test]
+    fn js_bundles_reexported_modules() {
+        let out = bundle_files(
+            Lang::Javascript,
+            &[
+                (
+                    "main.js",
+                    "import { helper } from \"./lib\";\nfunction robot() {}",
+                ),
+                ("lib/index.js", "export { helper } from \"../util\";"),
+                ("util.js", "export function helper() {}"),
+            ],
+        );
+        assert!(out.contains("\"lib/index.js\": function (module, exports) {"));
+        assert!(out.contains("\"util.js\": function (module, exports) {"));
+        assert!(out.contains("__rumblebot_load(\"util.js\")"));
+    }
+
+    #[test]
+    fn python_relative_imports() {
+        let out = bundle_files(
+            Lang::Python,
+            &[
+                ("main.py", "import pkg\n"),
+                (
+                    "pkg/__init__.py",
+                    "from . import helpers\nfrom .util import f\n",
+                ),
+                // `..other` is beyond the top-level package, so it isn't followed to `other`; Python
+                // raises an ImportError for it when it's run
+                (
+                    "pkg/helpers.py",
+                    "from .sub import deep\nfrom ..other import g\n",
+                ),
+                ("pkg/util.py", "def f(): pass\n"),
+                ("pkg/sub/__init__.py", "from ..util import f\n"),
+                ("pkg/sub/deep.py", "from .. import util\n"),
+                ("other.py", "def g(): pass\n"),
+            ],
+        );
+        for module in ["pkg", "pkg.helpers", "pkg.util", "pkg.sub", "pkg.sub.deep"] {
+            assert!(
+                out.contains(&format!("    {}: (", string_literal(module))),
+                "{} wasn't bundled",
+                module
+            );
+        }
+        assert!(!out.contains("\"other\": ("));
+    }
+
+    #[test]
+    fn python_future_imports_come_first() {
+        let out = bundle_files(
+            Lang::Python,
+            &[
+                (
+                    "main.py",
+                    "\"\"\"A robot\"\"\"\nfrom __future__ import annotations\nimport lib\n",
+                ),
+                ("lib.py", "x = 1\n"),
+            ],
+        );
+        let code = out.lines().find(|line| !line.starts_with('#')).unwrap();
+        assert_eq!(code, "from __future__ import annotations");
+        assert_eq!(out.matches("from __future__").count(), 1);
+    }
+
+    #[test]
+    fn python_modules_keep_their_paths() {
+        let out = bundle_files(
+            Lang::Python,
+            &[
+                ("main.py", "import pkg.mod\n"),
+                ("pkg/__init__.py", ""),
+                ("pkg/mod.py", "x = 1\n"),
+            ],
+        );
+        assert!(out.contains("\"pkg\": (True, \"pkg/__init__.py\", "));
+        assert!(out.contains("\"pkg.mod\": (False, \"pkg/mod.py\", "));
+    }
+}
@@ -0,0 +1,213 @@
+//! Native `command:` robots, which run as child processes, and the limits they run under. Since
+//! they can do anything a normal program can, the limits are the only thing keeping a misbehaving
+//! one from taking down the machine.
+
+use anyhow::Context;
+use logic::{ProgramInput, ProgramResult, RobotRunner};
+use native_runner::TokioRunner;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::process::Stdio;
+use structopt::StructOpt;
+use tokio::io::{BufReader, BufWriter};
+use tokio::process::{Child, ChildStdin, ChildStdout, Command};
+
+use super::logs::StderrCapture;
+
+/// Limits for `command:` robots. They don't apply to any other kind of robot, since those already
+/// run in a sandbox
+#[derive(StructOpt, Clone, Default)]
+pub struct CommandLimits {
+    /// Kill `command:` robots after they've used this many seconds of CPU time
+    #[structopt(long = "limit-cpu", value_name = "SECONDS")]
+    pub cpu_seconds: Option<u64>,
+    /// Limit the address space of `command:` robots to this many megabytes
+    #[structopt(long = "limit-memory", value_name = "MB")]
+    pub memory_mb: Option<u64>,
+    /// Limit how many files `command:` robots can have open at once
+    #[structopt(long = "limit-files", value_name = "N")]
+    pub open_files: Option<u64>,
+    /// Limit how many processes can be running as our user when a `command:` robot starts one,
+    /// which stops fork bombs. Note that this counts all of the user's processes, not just the
+    /// robot's
+    #[structopt(long = "limit-processes", value_name = "N")]
+    pub processes: Option<u64>,
+    /// Run `command:` robots in this directory, instead of the current one. On its own this only
+    /// sets their working directory; see `--command-jail`
+    #[structopt(long = "command-workdir", value_name = "DIR", parse(from_os_str))]
+    pub workdir: Option<PathBuf>,
+    /// Confine `command:` robots to `--command-workdir`, which becomes their root directory, so
+    /// that they can't get at any files outside of it. The command is looked up inside the
+    /// directory too, so it and everything it needs to run (an interpreter, shared libraries)
+    /// must be in there. Only supported on Linux, with unprivileged user namespaces enabled
+    #[structopt(long = "command-jail", requires = "workdir")]
+    pub jail: bool,
+    /// Pass our environment variables on to `command:` robots. By default they only get the ones
+    /// set for them with `--blue-env`/`--red-env`
+    #[structopt(long = "command-pass-env")]
+    pub pass_env: bool,
+}
+
+type Runner = TokioRunner<BufWriter<ChildStdin>, BufReader<ChildStdout>>;
+
+/// A running `command:` robot. The robot's whole process group is killed when this is dropped, so
+/// that nothing it started outlives the match
+pub struct CommandRobot {
+    runner: Runner,
+    child: Child,
+}
+
+impl CommandRobot {
+    pub async fn spawn(
+        command: &str,
+        args: &[String],
+        env: &BTreeMap<String, String>,
+        limits: &CommandLimits,
+        stderr: Option<&StderrCapture>,
+    ) -> anyhow::Result<ProgramResult<Self>> {
+        let mut cmd = Command::new(command);
+        cmd.args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(if stderr.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::inherit()
+            })
+            .kill_on_drop(true);
+        if !limits.pass_env {
+            cmd.env_clear();
+        }
+        cmd.envs(env);
+        if let Some(dir) = &limits.workdir {
+            cmd.current_dir(dir);
+        }
+        sys::isolate(&mut cmd, limits)?;
+
+        let mut child = cmd
+            .spawn()
+            .with_context(|| format!("Couldn't start {}", command))?;
+        let stdin = BufWriter::new(child.stdin.take().unwrap());
+        let stdout = BufReader::new(child.stdout.take().unwrap());
+        if let (Some(capture), Some(child_stderr)) = (stderr, child.stderr.take()) {
+            capture.spawn_reader(child_stderr);
+        }
+
+        let result = TokioRunner::new(stdin, stdout).await;
+        Ok(result.map(|runner| Self { runner, child }))
+    }
+}
+
+#[async_trait::async_trait]
+impl RobotRunner for CommandRobot {
+    async fn run(&mut self, input: ProgramInput<'_>) -> ProgramResult {
+        self.runner.run(input).await
+    }
+}
+
+impl Drop for CommandRobot {
+    fn drop(&mut self) {
+        sys::kill_group(&mut self.child);
+    }
+}
+
+#[cfg(unix)]
+mod sys {
+    use super::CommandLimits;
+    use anyhow::{anyhow, bail};
+    use std::io;
+    use tokio::process::{Child, Command};
+
+    pub fn isolate(cmd: &mut Command, limits: &CommandLimits) -> anyhow::Result<()> {
+        // its own process group, so that everything it starts can be killed along with it
+        cmd.process_group(0);
+        let memory = match limits.memory_mb {
+            Some(mb) => Some(
+                mb.checked_mul(1024 * 1024)
+                    .ok_or_else(|| anyhow!("--limit-memory {} is too large", mb))?,
+            ),
+            None => None,
+        };
+        let rlimits = [
+            (libc::RLIMIT_CPU, limits.cpu_seconds),
+            (libc::RLIMIT_AS, memory),
+            (libc::RLIMIT_NOFILE, limits.open_files),
+            (libc::RLIMIT_NPROC, limits.processes),
+        ];
+        let rlimits: Vec<_> = rlimits
+            .iter()
+            .filter_map(|&(resource, limit)| Some((resource, limit? as libc::rlim_t)))
+            .collect();
+        let jail = limits.jail;
+        if jail && !cfg!(target_os = "linux") {
+            bail!("--command-jail is only supported on Linux");
+        }
+        if rlimits.is_empty() && !jail {
+            return Ok(());
+        }
+        // SAFETY: these are all plain syscalls, which are async-signal-safe, and nothing is
+        // allocated after the fork
+        unsafe {
+            cmd.pre_exec(move || {
+                // this runs after the working directory is set, so the jail is the current
+                // directory. A new user namespace gives us the right to chroot without being root,
+                // and since we aren't root in it, the robot loses that right again when it's exec'd
+                #[cfg(target_os = "linux")]
+                if jail {
+                    if libc::unshare(libc::CLONE_NEWUSER | libc::CLONE_NEWNS) != 0
+                        || libc::chroot(b".\0".as_ptr().cast()) != 0
+                        || libc::chdir(b"/\0".as_ptr().cast()) != 0
+                        || libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) != 0
+                    {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                for &(resource, limit) in &rlimits {
+                    let rlimit = libc::rlimit {
+                        rlim_cur: limit,
+                        rlim_max: limit,
+                    };
+                    if libc::setrlimit(resource, &rlimit) != 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                }
+                Ok(())
+            });
+        }
+        Ok(())
+    }
+
+    pub fn kill_group(child: &mut Child) {
+        if let Some(pid) = child.id() {
+            // SAFETY: just a syscall; the group id is the child's pid because of `process_group(0)`
+            unsafe {
+                libc::killpg(pid as libc::pid_t, libc::SIGKILL);
+            }
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod sys {
+    use super::CommandLimits;
+    use anyhow::bail;
+    use tokio::process::{Child, Command};
+
+    pub fn isolate(_cmd: &mut Command, limits: &CommandLimits) -> anyhow::Result<()> {
+        let has_limits = limits.cpu_seconds.is_some()
+            || limits.memory_mb.is_some()
+            || limits.open_files.is_some()
+            || limits.processes.is_some();
+        if has_limits {
+            bail!("resource limits for command robots are only supported on unix");
+        }
+        if limits.jail {
+            bail!("--command-jail is only supported on Linux");
+        }
+        Ok(())
+    }
+
+    pub fn kill_group(child: &mut Child) {
+        let _ = child.start_kill();
+    }
+}
@@ -1,13 +1,64 @@
-use logic::{CallbackInput, Coords, GridMap, ObjDetails, ProgramError, Team, GRID_SIZE};
-use std::io::{self, Write};
-use termcolor::{BufferedStandardStream, Color, ColorSpec, WriteColor};
+use logic::{
+    Action, ActionResult, ActionType, CallbackInput, Coords, Direction, GridMap, ObjDetails,
+    ProgramError, Team, GRID_SIZE,
+};
+use once_cell::sync::OnceCell;
+use std::collections::{BTreeMap, VecDeque};
+use std::io::{self, IsTerminal, Write};
+use std::time::Duration;
+use termcolor::{
+    Buffer, BufferWriter, BufferedStandardStream, Color, ColorChoice, ColorSpec, WriteColor,
+};
+use tokio::sync::mpsc;
+
+#[derive(Clone, Copy, PartialEq, Eq, strum::EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum ColorMode {
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Clone, Copy)]
+struct Style {
+    color: ColorChoice,
+    /// Draw with plain ASCII characters, for consoles that mangle anything else
+    ascii: bool,
+}
+
+static STYLE: OnceCell<Style> = OnceCell::new();
+
+/// Set how all the output from this module is styled. Should be called once, before anything is
+/// printed
+pub fn set_style(color: ColorMode, ascii: bool) {
+    let no_color = std::env::var_os("NO_COLOR").map_or(false, |v| !v.is_empty());
+    let color = match color {
+        ColorMode::Always => ColorChoice::Always,
+        ColorMode::Never => ColorChoice::Never,
+        ColorMode::Auto if no_color || !io::stdout().is_terminal() => ColorChoice::Never,
+        ColorMode::Auto => ColorChoice::Auto,
+    };
+    let _ = STYLE.set(Style { color, ascii });
+}
+
+fn style() -> Style {
+    STYLE.get().copied().unwrap_or(Style {
+        color: ColorChoice::Auto,
+        ascii: false,
+    })
+}
+
+fn stdout() -> BufferedStandardStream {
+    BufferedStandardStream::stdout(style().color)
+}
 
 pub fn display_turn(
     turn: &CallbackInput,
+    board: BoardOptions,
     show_blue_logs: bool,
     show_red_logs: bool,
 ) -> io::Result<()> {
-    let mut out = BufferedStandardStream::stdout(termcolor::ColorChoice::Auto);
+    let mut out = stdout();
 
     let mut bold = ColorSpec::new();
     bold.set_bold(true);
@@ -19,67 +70,360 @@ pub fn display_turn(
     }
     out.reset()?;
 
+    if turn.state.turn == 1 {
+        write_legend(&mut out, board, "\n")?;
+    }
+    write_grid(&mut out, turn, &CellLayout::new(turn, board), "\n")?;
+
+    write_turn_info_values(&mut out, turn)?;
+    writeln!(out)?;
+
+    for (&team, logs) in &turn.logs {
+        if !logs.is_empty()
+            && ((show_blue_logs && team == logic::Team::Blue)
+                || (show_red_logs && team == logic::Team::Red))
+        {
+            let color = team_color(team);
+
+            let mut header = bold.clone();
+            header.set_fg(Some(color));
+            out.set_color(&header)?;
+            writeln!(out, "Logs for {:?}", team)?;
+
+            for log in logs.iter().flat_map(|log| log.trim_end().lines()) {
+                write_log_line(&mut out, team, log)?;
+                writeln!(out)?;
+            }
+        }
+    }
+
+    out.flush()?;
+    Ok(())
+}
+
+/// Redraws the board in place for every turn, rather than appending it to the scrollback. The
+/// frames are drawn as the game goes, and shown at the frame rate by the future from `new`, so
+/// that pacing them doesn't hold up the game
+pub struct Animation {
+    board: BoardOptions,
+    writer: BufferWriter,
+    frames: mpsc::UnboundedSender<Buffer>,
+    first_frame: bool,
+    logs: VecDeque<(Team, String)>,
+    /// Kept from frame to frame so that the board doesn't shift around when the widths change
+    layout: Option<CellLayout>,
+}
+
+/// How many lines of logs to keep on screen under the board
+const ANIMATION_LOG_LINES: usize = 10;
+
+// ANSI escape sequences
+const CURSOR_HOME: &str = "\x1b[H";
+const CLEAR_SCREEN: &str = "\x1b[2J";
+const CLEAR_LINE: &str = "\x1b[K";
+const CLEAR_BELOW: &str = "\x1b[J";
+
+impl Animation {
+    /// Also returns the future that shows the frames, which finishes once the animation is
+    /// dropped and every frame has been shown
+    pub fn new(
+        fps: f64,
+        board: BoardOptions,
+    ) -> (Self, impl std::future::Future<Output = io::Result<()>>) {
+        let (frames, rx) = mpsc::unbounded_channel();
+        let animation = Self {
+            board,
+            writer: BufferWriter::stdout(style().color),
+            frames,
+            first_frame: true,
+            logs: VecDeque::with_capacity(ANIMATION_LOG_LINES),
+            layout: None,
+        };
+        let frame_time = Duration::from_secs_f64(1.0 / fps);
+        (animation, show_frames(rx, frame_time))
+    }
+
+    pub fn draw(
+        &mut self,
+        turn: &CallbackInput,
+        show_blue_logs: bool,
+        show_red_logs: bool,
+    ) -> io::Result<()> {
+        for (&team, logs) in &turn.logs {
+            if (show_blue_logs && team == Team::Blue) || (show_red_logs && team == Team::Red) {
+                let lines = logs.iter().flat_map(|log| log.trim_end().lines());
+                self.logs.extend(lines.map(|line| (team, line.to_owned())));
+            }
+        }
+        let excess = self.logs.len().saturating_sub(ANIMATION_LOG_LINES);
+        self.logs.drain(..excess);
+
+        let mut out = self.writer.buffer();
+        if self.first_frame {
+            self.first_frame = false;
+            write!(out, "{}", CLEAR_SCREEN)?;
+        }
+        write!(out, "{}", CURSOR_HOME)?;
+
+        let layout = CellLayout::new(turn, self.board);
+        let layout = match self.layout.take() {
+            Some(prev) => prev.widen(layout),
+            None => layout,
+        };
+        let eol = format!("{}\n", CLEAR_LINE);
+        write_grid(&mut out, turn, &layout, &eol)?;
+        self.layout = Some(layout);
+        write_legend(&mut out, self.board, &eol)?;
+
+        let mut bold = ColorSpec::new();
+        bold.set_bold(true);
+        out.set_color(&bold)?;
+        write!(out, "Turn {}", turn.state.turn)?;
+        out.reset()?;
+        write!(out, " | ")?;
+        write_turn_info_values(&mut out, turn)?;
+        write!(out, "{}", eol)?;
+        write!(out, "{}", eol)?;
+
+        for (team, line) in &self.logs {
+            write_log_line(&mut out, *team, line)?;
+            write!(out, "{}", eol)?;
+        }
+        write!(out, "{}", CLEAR_BELOW)?;
+
+        // if showing the frames failed, that error is returned from there
+        let _ = self.frames.send(out);
+        Ok(())
+    }
+}
+
+async fn show_frames(
+    mut frames: mpsc::UnboundedReceiver<Buffer>,
+    frame_time: Duration,
+) -> io::Result<()> {
+    let writer = BufferWriter::stdout(style().color);
+    let mut ticks = tokio::time::interval(frame_time);
+    // a slow turn shouldn't make the frames after it rush to catch up
+    ticks.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+    while let Some(frame) = frames.recv().await {
+        ticks.tick().await;
+        writer.print(&frame)?;
+    }
+    Ok(())
+}
+
+/// Writes the board, ending each row with `eol`
+fn write_grid(
+    out: &mut impl WriteColor,
+    turn: &CallbackInput,
+    layout: &CellLayout,
+    eol: &str,
+) -> io::Result<()> {
+    let style = style();
+    let board = layout.board;
+    let wall = if style.ascii { "#" } else { "█" };
     let grid_map = GridMap::from(&turn.state.objs);
     for y in 0..GRID_SIZE {
         let mut first = true;
         let mut prev_wall = false;
         for x in 0..GRID_SIZE {
-            let details = grid_map
-                .get(&Coords(x, y))
-                .map(|id| turn.state.objs.get(&id).unwrap().details());
-            let cur_wall = matches!(details, Some(ObjDetails::Terrain(_)));
+            let cell = grid_map.get(&Coords(x, y)).map(|id| {
+                let obj = turn.state.objs.get(&id).unwrap();
+                (obj, turn.robot_actions.get(&id))
+            });
+            let cur_wall =
+                matches!(cell, Some((obj, _)) if matches!(obj.details(), ObjDetails::Terrain(_)));
             if first {
                 first = false
             } else if prev_wall && cur_wall {
-                write!(out, "█")?;
+                write!(out, "{}", wall)?;
             } else {
                 write!(out, " ")?;
             }
             prev_wall = cur_wall;
-            match details {
-                Some(ObjDetails::Terrain(_)) => write!(out, "█")?,
-                Some(ObjDetails::Unit(unit)) => {
+            match cell.map(|(obj, action)| (obj, obj.details(), action)) {
+                Some((_, ObjDetails::Terrain(_), _)) => {
+                    write!(out, "{}", wall.repeat(layout.width))?
+                }
+                Some((obj, ObjDetails::Unit(unit), action)) => {
                     let mut spec = ColorSpec::new();
                     spec.set_bg(Some(team_color(unit.team)));
                     // spec.set_fg(Some(Color::White));
                     out.set_color(&spec)?;
-                    write!(out, "{}", unit.health)?;
+                    if board.detailed && board.unit_ids {
+                        write!(out, "{:>w$}:", obj.0.id.0, w = layout.id_width)?;
+                    }
+                    if layout.team_letters {
+                        write!(
+                            out,
+                            "{}{:>w$}",
+                            team_letter(unit.team),
+                            unit.health,
+                            w = layout.health_width,
+                        )?;
+                    } else {
+                        write!(out, "{}", unit.health)?;
+                    }
+                    if board.detailed {
+                        write!(out, "{}", action_marker(action, style.ascii))?;
+                    }
                     out.reset()?;
                 }
-                None => write!(out, " ")?,
+                None => write!(out, "{:w$}", "", w = layout.width)?,
             };
         }
-        writeln!(out)?;
+        write!(out, "{}", eol)?;
     }
+    Ok(())
+}
 
-    write_turn_info_values(&mut out, turn)?;
-    writeln!(out)?;
+/// Options for how the board is drawn
+#[derive(Clone, Copy, Default)]
+pub struct BoardOptions {
+    /// Give every cell the same width, and show the unit's team and what it's doing this turn
+    pub detailed: bool,
+    /// Also show the unit's id in each cell. Only used when `detailed` is set
+    pub unit_ids: bool,
+}
 
-    for (&team, logs) in &turn.logs {
-        if !logs.is_empty()
-            && ((show_blue_logs && team == logic::Team::Blue)
-                || (show_red_logs && team == logic::Team::Red))
-        {
-            let color = team_color(team);
+/// The widths of the different parts of a cell, so that every cell on the board lines up
+#[derive(Clone, Copy)]
+struct CellLayout {
+    board: BoardOptions,
+    /// Whether each unit is labelled with its team's letter
+    team_letters: bool,
+    width: usize,
+    id_width: usize,
+    health_width: usize,
+}
 
-            let mut header = bold.clone();
-            header.set_fg(Some(color));
-            out.set_color(&header)?;
-            writeln!(out, "Logs for {:?}", team)?;
+impl CellLayout {
+    fn new(turn: &CallbackInput, board: BoardOptions) -> Self {
+        let style = style();
+        // without color, the letter is the only way to tell the teams apart
+        let team_letters = board.detailed || style.ascii || style.color == ColorChoice::Never;
+        if !board.detailed && !team_letters {
+            return Self {
+                board,
+                team_letters,
+                width: 1,
+                id_width: 0,
+                health_width: 0,
+            };
+        }
+        let digits = |n: usize| n.to_string().len();
+        let health_width = turn
+            .state
+            .objs
+            .values()
+            .filter_map(|obj| match obj.details() {
+                ObjDetails::Unit(unit) => Some(digits(unit.health)),
+                _ => None,
+            })
+            .max()
+            .unwrap_or(1);
+        // ids are handed out as objects are created, so leave room for about as many as fit on
+        // the board rather than just the ones there are so far
+        let id_width = if board.detailed && board.unit_ids {
+            turn.state
+                .objs
+                .keys()
+                .map(|id| digits(id.0))
+                .chain(Some(digits(GRID_SIZE * GRID_SIZE)))
+                .max()
+                .unwrap_or(1)
+        } else {
+            0
+        };
+        // team letter + health, then the action marker, and the id with a colon after it
+        let mut width = 1 + health_width;
+        if board.detailed {
+            width += 1;
+        }
+        if id_width > 0 {
+            width += id_width + 1;
+        }
+        Self {
+            board,
+            team_letters,
+            width,
+            id_width,
+            health_width,
+        }
+    }
 
-            let mut bg = ColorSpec::new();
-            bg.set_bg(Some(color));
-            for log in logs.iter().flat_map(|log| log.trim_end().lines()) {
-                out.set_color(&bg)?;
-                write!(out, "|{:?}|", team)?;
-                out.reset()?;
-                writeln!(out, " {}", log)?;
-            }
+    /// A layout that fits the cells of both, so that the cells never get narrower. Units are at
+    /// full health when they're spawned, so the health width stays the same after the first turn
+    fn widen(self, other: Self) -> Self {
+        Self {
+            width: self.width.max(other.width),
+            id_width: self.id_width.max(other.id_width),
+            health_width: self.health_width.max(other.health_width),
+            ..self
         }
     }
+}
 
-    out.flush()?;
-    Ok(())
+fn team_letter(team: Team) -> char {
+    match team {
+        Team::Red => 'R',
+        Team::Blue => 'B',
+    }
+}
+
+fn action_marker(action: Option<&ActionResult>, ascii: bool) -> char {
+    let pick = |unicode, plain| if ascii { plain } else { unicode };
+    match action {
+        Some(Ok(Some(Action {
+            type_: ActionType::Move,
+            direction,
+        }))) => match direction {
+            Direction::North => pick('↑', '^'),
+            Direction::South => pick('↓', 'v'),
+            Direction::East => pick('→', '>'),
+            Direction::West => pick('←', '<'),
+        },
+        Some(Ok(Some(Action {
+            type_: ActionType::Attack,
+            direction,
+        }))) => match direction {
+            Direction::North => pick('⇑', 'n'),
+            Direction::South => pick('⇓', 's'),
+            Direction::East => pick('⇒', 'e'),
+            Direction::West => pick('⇐', 'w'),
+        },
+        Some(Err(_)) => '!',
+        Some(Ok(None)) | None => ' ',
+    }
+}
+
+fn write_legend(out: &mut impl WriteColor, board: BoardOptions, eol: &str) -> io::Result<()> {
+    if !board.detailed {
+        return Ok(());
+    }
+    write!(out, "Legend: ")?;
+    if board.unit_ids {
+        write!(out, "id:")?;
+    }
+    let (moves, attacks) = if style().ascii {
+        ("^v<>", "nsew")
+    } else {
+        ("↑↓←→", "⇑⇓⇐⇒")
+    };
+    write!(
+        out,
+        "B/R team, then health; {} move; {} attack; ! invalid action{}",
+        moves, attacks, eol
+    )
+}
+
+fn write_log_line(out: &mut impl WriteColor, team: Team, line: &str) -> io::Result<()> {
+    let mut bg = ColorSpec::new();
+    bg.set_bg(Some(team_color(team)));
+    out.set_color(&bg)?;
+    write!(out, "|{:?}|", team)?;
+    out.reset()?;
+    write!(out, " {}", line)
 }
 
 fn team_color(team: Team) -> Color {
@@ -124,7 +468,7 @@ fn compute_turn_info_values(turn_state: &logic::CallbackInput) -> (usize, usize,
 }
 
 pub fn write_turn_info_values(
-    out: &mut BufferedStandardStream,
+    out: &mut impl WriteColor,
     turn_state: &logic::CallbackInput,
 ) -> io::Result<()> {
     let (rc, bc, rh, bh) = compute_turn_info_values(turn_state);
@@ -151,7 +495,19 @@ pub fn write_turn_info_values(
     Ok(())
 }
 
-pub fn display_output(output: logic::MainOutput) -> io::Result<()> {
+/// A robot's source code, used to show where its errors happened
+pub struct SourceFile {
+    pub name: String,
+    pub code: String,
+}
+
+/// `error_turns` is the turn each team's robot errored on, for the robots that got as far as
+/// running
+pub fn display_output(
+    output: logic::MainOutput,
+    sources: &BTreeMap<Team, SourceFile>,
+    error_turns: &BTreeMap<Team, usize>,
+) -> io::Result<()> {
     if let Some(w) = output.winner {
         println!("Done! {:?} won", w);
     } else {
@@ -160,31 +516,346 @@ pub fn display_output(output: logic::MainOutput) -> io::Result<()> {
 
     print!("Final state: ");
 
+    let mut out = stdout();
     if let Some(final_turn) = output.turns.last() {
-        let mut out = BufferedStandardStream::stdout(termcolor::ColorChoice::Auto);
         write_turn_info_values(&mut out, final_turn)?;
     }
+    writeln!(out)?;
 
     if !output.errors.is_empty() {
-        println!("Some errors occurred:");
-        for (team, error) in output.errors {
-            println!("  {:?}:", team);
-            display_error(error)
+        writeln!(out, "Some errors occurred:")?;
+        for (&team, error) in &output.errors {
+            writeln!(out, "  {:?}:", team)?;
+            let turn = error_turns.get(&team).copied();
+            display_error(&mut out, error, Some(team), turn, sources.get(&team))?;
         }
     }
 
-    Ok(())
+    // a mistake in a robot usually happens to all of its units, every turn, so each one is only
+    // shown the first time it happened
+    let mut unit_errors = BTreeMap::new();
+    for turn in &output.turns {
+        for (id, action) in &turn.robot_actions {
+            let error = match action {
+                Err(error) => error,
+                Ok(_) => continue,
+            };
+            let team = match turn.state.objs.get(id).map(|obj| obj.details()) {
+                Some(ObjDetails::Unit(unit)) => unit.team,
+                _ => continue,
+            };
+            unit_errors
+                .entry((team, &error.summary))
+                .or_insert((turn.state.turn, id, error, 0))
+                .3 += 1;
+        }
+    }
+    if !unit_errors.is_empty() {
+        writeln!(out, "Some units errored:")?;
+        for ((team, _), (turn, id, error, count)) in unit_errors {
+            write!(out, "  {:?} unit {} on turn {}", team, id.0, turn)?;
+            if count > 1 {
+                write!(out, ", and {} more times", count - 1)?;
+            }
+            writeln!(out, ":")?;
+            write_error_details(
+                &mut out,
+                &error.summary,
+                error.details.as_deref(),
+                error.loc.as_ref(),
+                sources.get(&team),
+            )?;
+        }
+    }
+
+    out.flush()
 }
 
-fn display_error(err: ProgramError) {
-    match err {
-        ProgramError::InitError(error) => {
-            let indent = |s| textwrap::indent(s, "    ");
-            println!("{}", indent(&error.summary));
-            if let Some(details) = error.details {
-                println!("{}", indent(&details));
+/// Show the result of `rumblebot check` for a single robot
+pub fn display_check(
+    name: &str,
+    result: &logic::ProgramResult<()>,
+    time: Duration,
+    source: Option<SourceFile>,
+) -> io::Result<()> {
+    let mut out = stdout();
+    let mut spec = ColorSpec::new();
+    spec.set_bold(true);
+    out.set_color(&spec)?;
+    write!(out, "{}: ", name)?;
+    match result {
+        Ok(()) => {
+            spec.set_fg(Some(Color::Green));
+            out.set_color(&spec)?;
+            write!(out, "ok")?;
+            out.reset()?;
+            writeln!(out, " ({:.2?})", time)?;
+        }
+        Err(err) => {
+            spec.set_fg(Some(Color::Red));
+            out.set_color(&spec)?;
+            writeln!(out, "failed to initialize")?;
+            out.reset()?;
+            display_error(&mut out, err, None, None, source.as_ref())?;
+        }
+    }
+    out.flush()
+}
+
+/// Show the best `top` entries of the `run tune` leaderboard
+pub fn display_leaderboard(entries: &[crate::tune::Entry], top: usize) -> io::Result<()> {
+    let mut out = stdout();
+    let mut spec = ColorSpec::new();
+    spec.set_bold(true);
+    out.set_color(&spec)?;
+    writeln!(
+        out,
+        "{:>4}  {:>8}  {:>11}  params",
+        "rank", "win rate", "W/T/L"
+    )?;
+    out.reset()?;
+    for (i, entry) in entries.iter().take(top).enumerate() {
+        let record = format!("{}/{}/{}", entry.wins, entry.ties, entry.losses);
+        writeln!(
+            out,
+            "{:>4}  {:>7.1}%  {:>11}  {}",
+            i + 1,
+            entry.win_rate * 100.0,
+            record,
+            crate::tune::format_params(&entry.params)
+        )?;
+    }
+    out.flush()
+}
+
+fn format_rating(rating: Option<f64>) -> String {
+    rating.map_or_else(|| "-".to_owned(), |r| format!("{:.0}", r))
+}
+
+pub fn display_robots(robots: &[crate::api::RobotSummary]) -> io::Result<()> {
+    let mut out = stdout();
+    let name_width = robots
+        .iter()
+        .map(|r| r.name.len())
+        .max()
+        .unwrap_or(0)
+        .max(4);
+    let mut spec = ColorSpec::new();
+    spec.set_bold(true);
+    out.set_color(&spec)?;
+    writeln!(
+        out,
+        "{:<name_width$}  {:<10}  {:>8}  {:<9}  {:>6}",
+        "name",
+        "lang",
+        "id",
+        "published",
+        "rating",
+        name_width = name_width
+    )?;
+    out.reset()?;
+    for robot in robots {
+        writeln!(
+            out,
+            "{:<name_width$}  {:<10}  {:>8}  {:<9}  {:>6}",
+            robot.name,
+            robot.lang.as_ref(),
+            robot.id,
+            if robot.published { "yes" } else { "no" },
+            format_rating(robot.rating),
+            name_width = name_width
+        )?;
+    }
+    out.flush()
+}
+
+pub fn display_robot_details(user: &str, details: &crate::api::RobotDetails) -> io::Result<()> {
+    let mut out = stdout();
+    let robot = &details.summary;
+    let mut spec = ColorSpec::new();
+    spec.set_bold(true);
+    out.set_color(&spec)?;
+    writeln!(out, "{}/{}", user, robot.name)?;
+    out.reset()?;
+    if let Some(description) = details.description.as_deref().filter(|d| !d.is_empty()) {
+        writeln!(out, "{}", textwrap::indent(description, "  "))?;
+    }
+    let published = if robot.published { "yes" } else { "no" };
+    let rating = format_rating(robot.rating);
+    let fields = [
+        ("id", Some(robot.id.to_string())),
+        ("lang", Some(robot.lang.as_ref().to_owned())),
+        ("published", Some(published.to_owned())),
+        ("rating", Some(rating)),
+        ("created", details.created.clone()),
+        ("modified", details.modified.clone()),
+    ];
+    for (label, value) in fields.iter() {
+        if let Some(value) = value {
+            writeln!(out, "  {:<10} {}", format!("{}:", label), value)?;
+        }
+    }
+    out.flush()
+}
+
+/// A unified diff from `old` to `new`, with removed lines in red and added ones in green
+pub fn display_diff(old_name: &str, new_name: &str, old: &str, new: &str) -> io::Result<()> {
+    use similar::{ChangeTag, TextDiff};
+    let mut out = stdout();
+    let diff = TextDiff::from_lines(old, new);
+    let mut bold = ColorSpec::new();
+    bold.set_bold(true);
+    out.set_color(&bold)?;
+    writeln!(out, "--- {}\n+++ {}", old_name, new_name)?;
+    let mut hunk_header = ColorSpec::new();
+    hunk_header.set_fg(Some(Color::Cyan));
+    for hunk in diff.unified_diff().context_radius(3).iter_hunks() {
+        out.set_color(&hunk_header)?;
+        writeln!(out, "{}", hunk.header())?;
+        for change in hunk.iter_changes() {
+            let (sign, color) = match change.tag() {
+                ChangeTag::Delete => ('-', Some(Color::Red)),
+                ChangeTag::Insert => ('+', Some(Color::Green)),
+                ChangeTag::Equal => (' ', None),
+            };
+            let mut spec = ColorSpec::new();
+            spec.set_fg(color);
+            out.set_color(&spec)?;
+            write!(out, "{}{}", sign, change.value())?;
+            if change.missing_newline() {
+                writeln!(out, "\n\\ No newline at end of file")?;
             }
         }
-        _ => println!("    {:?}", err),
     }
+    out.reset()?;
+    out.flush()
+}
+
+/// `team` is the team that errored if it happened in a game, and `turn` the turn it happened on
+/// if the game got as far as running the robots
+fn display_error(
+    out: &mut BufferedStandardStream,
+    err: &ProgramError,
+    team: Option<Team>,
+    turn: Option<usize>,
+    source: Option<&SourceFile>,
+) -> io::Result<()> {
+    let indent = |s: &str| textwrap::indent(s, "    ");
+    let robot = match team {
+        Some(team) => format!("The {:?} robot", team),
+        None => "The robot".to_owned(),
+    };
+    let on_turn = match turn {
+        Some(turn) => format!(" on turn {}", turn),
+        None => String::new(),
+    };
+    match err {
+        ProgramError::InitError(error) => write_error_details(
+            out,
+            &error.summary,
+            error.details.as_deref(),
+            error.loc.as_ref(),
+            source,
+        ),
+        ProgramError::Timeout(dur) => {
+            writeln!(out, "    {} timed out after {:?}{}", robot, dur, on_turn)
+        }
+        ProgramError::IO(msg) => {
+            writeln!(
+                out,
+                "    {} couldn't be communicated with{}:",
+                robot, on_turn
+            )?;
+            writeln!(out, "{}", indent(msg))
+        }
+        _ => writeln!(out, "    {:?}{}", err, on_turn),
+    }
+}
+
+/// An error from the robot's own code, with where it happened in `source` if we know
+fn write_error_details(
+    out: &mut BufferedStandardStream,
+    summary: &str,
+    details: Option<&str>,
+    loc: Option<&logic::ErrorLoc>,
+    source: Option<&SourceFile>,
+) -> io::Result<()> {
+    let indent = |s: &str| textwrap::indent(s, "    ");
+    if let (Some(loc), Some(source)) = (loc, source) {
+        write_code_frame(out, source, loc)?;
+    }
+    let mut bold = ColorSpec::new();
+    bold.set_bold(true);
+    out.set_color(&bold)?;
+    writeln!(out, "{}", indent(summary))?;
+    out.reset()?;
+    if let Some(details) = details {
+        writeln!(out, "{}", indent(details))?;
+    }
+    Ok(())
+}
+
+/// Show the lines around where an error happened, with the error's location underlined
+fn write_code_frame(
+    out: &mut BufferedStandardStream,
+    source: &SourceFile,
+    loc: &logic::ErrorLoc,
+) -> io::Result<()> {
+    const CONTEXT_LINES: usize = 2;
+
+    let (line, col) = loc.start;
+    let lines: Vec<&str> = source.code.lines().collect();
+    if line == 0 || line > lines.len() {
+        return Ok(());
+    }
+
+    let mut gutter = ColorSpec::new();
+    gutter.set_fg(Some(Color::Blue)).set_bold(true);
+    let mut marker = ColorSpec::new();
+    marker.set_fg(Some(Color::Red)).set_bold(true);
+
+    let first = line.saturating_sub(CONTEXT_LINES).max(1);
+    let last = (line + CONTEXT_LINES).min(lines.len());
+    let width = last.to_string().len();
+
+    out.set_color(&gutter)?;
+    write!(out, "    {:w$}--> ", "", w = width)?;
+    out.reset()?;
+    match col {
+        Some(col) => writeln!(out, "{}:{}:{}", source.name, line, col)?,
+        None => writeln!(out, "{}:{}", source.name, line)?,
+    }
+
+    for n in first..=last {
+        let text = lines[n - 1];
+        out.set_color(&gutter)?;
+        write!(out, "    {:>w$} | ", n, w = width)?;
+        out.reset()?;
+        writeln!(out, "{}", text)?;
+        if n != line {
+            continue;
+        }
+        // columns count characters, not bytes
+        let indentation = text.chars().take_while(|c| c.is_whitespace()).count();
+        let (start, len) = match (col, loc.end) {
+            (Some(col), Some((end_line, Some(end_col)))) if end_line == line && end_col > col => {
+                (col.saturating_sub(1), end_col - col)
+            }
+            (Some(col), _) => (col.saturating_sub(1), 1),
+            (None, _) => (indentation, text.trim().chars().count().max(1)),
+        };
+        // keep any tabs before the marker, so that it lines up however wide they're shown
+        let padding: String = text
+            .chars()
+            .chain(std::iter::repeat(' '))
+            .take(start)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        out.set_color(&gutter)?;
+        write!(out, "    {:w$} | ", "", w = width)?;
+        out.set_color(&marker)?;
+        writeln!(out, "{}{}", padding, "^".repeat(len))?;
+        out.reset()?;
+    }
+    Ok(())
 }
@@ -0,0 +1,181 @@
+use anyhow::Context;
+use std::borrow::Cow;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use super::{exit_code, ExitStatus};
+
+pub enum Outcome {
+    Passed,
+    /// The match finished, but not the way it was expected to
+    Failed(String),
+    /// A robot errored, or the match couldn't be run at all
+    Errored(String),
+}
+
+impl Outcome {
+    pub fn from_status(status: &Result<(), ExitStatus>) -> Self {
+        match status {
+            Ok(()) => Self::Passed,
+            Err(status) if status.code == exit_code::UNEXPECTED_RESULT => {
+                Self::Failed(status.msg.clone())
+            }
+            Err(status) => Self::Errored(status.msg.clone()),
+        }
+    }
+}
+
+pub struct TestCase {
+    pub name: String,
+    pub time: Duration,
+    pub outcome: Outcome,
+}
+
+pub fn write_report(path: &Path, cases: &[TestCase]) -> anyhow::Result<()> {
+    let xml = render(cases).expect("writing to a String can't fail");
+    fs::write(path, xml).with_context(|| format!("Couldn't write {}", path.display()))
+}
+
+fn render(cases: &[TestCase]) -> Result<String, std::fmt::Error> {
+    let count = |f: fn(&Outcome) -> bool| cases.iter().filter(|c| f(&c.outcome)).count();
+    let failures = count(|o| matches!(o, Outcome::Failed(_)));
+    let errors = count(|o| matches!(o, Outcome::Errored(_)));
+    let time: Duration = cases.iter().map(|c| c.time).sum();
+
+    let mut xml = String::new();
+    writeln!(xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        xml,
+        r#"<testsuite name="rumblebot" tests="{}" failures="{}" errors="{}" time="{:.3}">"#,
+        cases.len(),
+        failures,
+        errors,
+        time.as_secs_f64(),
+    )?;
+    for case in cases {
+        write!(
+            xml,
+            r#"  <testcase classname="rumblebot" name="{}" time="{:.3}""#,
+            escape(&case.name),
+            case.time.as_secs_f64(),
+        )?;
+        match &case.outcome {
+            Outcome::Passed => writeln!(xml, "/>")?,
+            Outcome::Failed(msg) => writeln!(
+                xml,
+                ">\n    <failure message=\"{}\"/>\n  </testcase>",
+                escape(msg)
+            )?,
+            Outcome::Errored(msg) => writeln!(
+                xml,
+                ">\n    <error message=\"{}\"/>\n  </testcase>",
+                escape(msg)
+            )?,
+        }
+    }
+    writeln!(xml, "</testsuite>")?;
+    Ok(xml)
+}
+
+/// Escapes text for an attribute. XML 1.0 can't have control characters other than tabs and line
+/// breaks at all, even as references, so the rest (like the escape codes from colored output) are
+/// replaced
+fn escape(s: &str) -> Cow<'_, str> {
+    if !s.contains(|c: char| matches!(c, '&' | '<' | '>' | '"' | '\'') || c < ' ') {
+        return s.into();
+    }
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            '\n' => escaped.push_str("&#10;"),
+            '\r' => escaped.push_str("&#13;"),
+            '\t' => escaped.push_str("&#9;"),
+            c if c < ' ' => escaped.push(char::REPLACEMENT_CHARACTER),
+            c => escaped.push(c),
+        }
+    }
+    escaped.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn case(name: &str, outcome: Outcome) -> TestCase {
+        TestCase {
+            name: name.to_owned(),
+            time: Duration::from_millis(1500),
+            outcome,
+        }
+    }
+
+    #[test]
+    fn escapes_markup_and_quotes() {
+        assert_eq!(escape("a vs b"), "a vs b");
+        assert_eq!(
+            escape(r#"<a> & "b" 'c'"#),
+            "&lt;a&gt; &amp; &quot;b&quot; &apos;c&apos;"
+        );
+        // already escaped text is escaped again, since it's what the message actually says
+        assert_eq!(escape("&amp;"), "&amp;amp;");
+    }
+
+    #[test]
+    fn escapes_control_characters() {
+        assert_eq!(escape("a\nb\r\tc"), "a&#10;b&#13;&#9;c");
+        assert_eq!(escape("\u{1b}[31mred\u{0}"), "\u{fffd}[31mred\u{fffd}");
+    }
+
+    #[test]
+    fn renders_every_outcome() {
+        let xml = render(&[
+            case("a vs b", Outcome::Passed),
+            case("a vs <c>", Outcome::Failed("expected a to win".to_owned())),
+            case(
+                "c vs d",
+                Outcome::Errored("c errored:\n\"oops\"".to_owned()),
+            ),
+        ])
+        .unwrap();
+        assert_eq!(
+            xml,
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<testsuite name="rumblebot" tests="3" failures="1" errors="1" time="4.500">
+  <testcase classname="rumblebot" name="a vs b" time="1.500"/>
+  <testcase classname="rumblebot" name="a vs &lt;c&gt;" time="1.500">
+    <failure message="expected a to win"/>
+  </testcase>
+  <testcase classname="rumblebot" name="c vs d" time="1.500">
+    <error message="c errored:&#10;&quot;oops&quot;"/>
+  </testcase>
+</testsuite>
+"#
+        );
+    }
+
+    #[test]
+    fn outcomes_follow_the_exit_status() {
+        let status = |code| {
+            Err(ExitStatus {
+                code,
+                msg: "msg".to_owned(),
+            })
+        };
+        assert!(matches!(Outcome::from_status(&Ok(())), Outcome::Passed));
+        assert!(matches!(
+            Outcome::from_status(&status(exit_code::UNEXPECTED_RESULT)),
+            Outcome::Failed(msg) if msg == "msg"
+        ));
+        assert!(matches!(
+            Outcome::from_status(&status(exit_code::ROBOT_ERROR)),
+            Outcome::Errored(msg) if msg == "msg"
+        ));
+    }
+}
@@ -0,0 +1,208 @@
+use anyhow::Context;
+use logic::{CallbackInput, ProgramError, Team};
+use std::collections::{BTreeMap, VecDeque};
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::task::JoinHandle;
+
+/// How many lines of a robot's stderr to attach to its error
+const TAIL_LINES: usize = 20;
+
+/// How long to wait for a robot's stderr to be closed after it was stopped. Something it started
+/// could keep the pipe open, so we can't wait for that forever
+const DRAIN_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Collects everything a robot writes to stderr
+#[derive(Clone)]
+pub struct StderrCapture {
+    /// What to prefix the lines with when forwarding them, usually the robot's team
+    label: Arc<str>,
+    inner: Arc<Mutex<CaptureInner>>,
+}
+
+#[derive(Default)]
+struct CaptureInner {
+    tail: VecDeque<String>,
+    /// Lines that haven't been written to the log file yet, if there is one
+    pending: Option<Vec<String>>,
+    reader: Option<JoinHandle<()>>,
+}
+
+impl StderrCapture {
+    pub fn new(label: impl Into<Arc<str>>) -> Self {
+        Self {
+            label: label.into(),
+            inner: Default::default(),
+        }
+    }
+
+    pub fn for_team(team: Team) -> Self {
+        Self::new(format!("{:?}", team))
+    }
+
+    /// Read from `stderr` until it's closed, forwarding each line to our own stderr with the
+    /// label as a prefix
+    pub fn spawn_reader(&self, stderr: impl AsyncRead + Send + Unpin + 'static) {
+        let capture = self.clone();
+        let reader = tokio::spawn(async move {
+            let mut lines = BufReader::new(stderr).lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                eprintln!("[{}] {}", capture.label, line);
+                let mut inner = capture.inner.lock().unwrap();
+                if inner.tail.len() == TAIL_LINES {
+                    inner.tail.pop_front();
+                }
+                if let Some(pending) = &mut inner.pending {
+                    pending.push(line.clone());
+                }
+                inner.tail.push_back(line);
+            }
+        });
+        self.inner.lock().unwrap().reader = Some(reader);
+    }
+
+    /// Wait for the reader to get to the end of the robot's stderr, so that `tail` and the log
+    /// files have everything it printed before it stopped
+    pub async fn drain(&self) {
+        let reader = self.inner.lock().unwrap().reader.take();
+        if let Some(reader) = reader {
+            let _ = tokio::time::timeout(DRAIN_TIMEOUT, reader).await;
+        }
+    }
+
+    /// The last few lines the robot wrote to stderr
+    pub fn tail(&self) -> Vec<String> {
+        self.inner.lock().unwrap().tail.iter().cloned().collect()
+    }
+
+    /// Start keeping every line so that it can be written to a `LogFiles`
+    pub fn keep_for_log(&self) {
+        self.inner
+            .lock()
+            .unwrap()
+            .pending
+            .get_or_insert_with(Vec::new);
+    }
+
+    fn take_pending(&self) -> Vec<String> {
+        let mut inner = self.inner.lock().unwrap();
+        inner
+            .pending
+            .as_mut()
+            .map(std::mem::take)
+            .unwrap_or_default()
+    }
+}
+
+/// Add the end of a robot's stderr to its error, since that's usually where the interesting part
+/// of a traceback is. Errors that don't carry any text are left as they are
+pub fn attach_stderr(err: &mut ProgramError, tail: &[String]) {
+    if tail.is_empty() {
+        return;
+    }
+    let tail = format!("stderr:\n{}", tail.join("\n"));
+    match err {
+        ProgramError::InitError(error) => {
+            error.details = Some(match error.details.take() {
+                Some(details) => format!("{}\n{}", details.trim_end(), tail),
+                None => tail,
+            })
+        }
+        ProgramError::IO(msg) => *msg = format!("{}\n{}", msg, tail),
+        _ => {}
+    }
+}
+
+/// `blue.log` and `red.log` in the directory passed with `--log-dir`
+pub struct LogFiles {
+    files: BTreeMap<Team, BufWriter<File>>,
+}
+
+impl LogFiles {
+    pub fn create(dir: &Path) -> anyhow::Result<Self> {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("Couldn't create log directory {}", dir.display()))?;
+        let mut files = BTreeMap::new();
+        for &team in &[Team::Blue, Team::Red] {
+            let path = dir.join(format!("{}.log", format!("{:?}", team).to_lowercase()));
+            let file = File::create(&path)
+                .with_context(|| format!("Couldn't create {}", path.display()))?;
+            files.insert(team, BufWriter::new(file));
+        }
+        Ok(Self { files })
+    }
+
+    pub fn write_turn(
+        &mut self,
+        turn: &CallbackInput,
+        stderr: &BTreeMap<Team, StderrCapture>,
+    ) -> io::Result<()> {
+        let prefix = format!("[turn {}]", turn.state.turn);
+        for (team, file) in &mut self.files {
+            if let Some(capture) = stderr.get(team) {
+                for line in capture.take_pending() {
+                    writeln!(file, "{} [stderr] {}", prefix, line)?;
+                }
+            }
+            for log in turn.logs.get(team).into_iter().flatten() {
+                for line in log.trim_end().lines() {
+                    writeln!(file, "{} {}", prefix, line)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Write out whatever the robots printed after the last turn, e.g. a traceback from the turn
+    /// that errored
+    pub fn finish(mut self, stderr: &BTreeMap<Team, StderrCapture>) -> io::Result<()> {
+        for (team, file) in &mut self.files {
+            if let Some(capture) = stderr.get(team) {
+                for line in capture.take_pending() {
+                    writeln!(file, "[end] [stderr] {}", line)?;
+                }
+            }
+            file.flush()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{StderrCapture, TAIL_LINES};
+    use std::io::Cursor;
+
+    fn lines(n: usize) -> Cursor<Vec<u8>> {
+        let text: String = (0..n).map(|i| format!("line {}\n", i)).collect();
+        Cursor::new(text.into_bytes())
+    }
+
+    #[tokio::test]
+    async fn only_keeps_the_tail_without_a_log() {
+        let capture = StderrCapture::new("test");
+        capture.spawn_reader(lines(TAIL_LINES * 5));
+        capture.drain().await;
+        let tail = capture.tail();
+        assert_eq!(tail.len(), TAIL_LINES);
+        assert_eq!(
+            tail.last().unwrap(),
+            &format!("line {}", TAIL_LINES * 5 - 1)
+        );
+        assert!(capture.inner.lock().unwrap().pending.is_none());
+    }
+
+    #[tokio::test]
+    async fn keeps_everything_for_a_log() {
+        let capture = StderrCapture::new("test");
+        capture.keep_for_log();
+        capture.spawn_reader(lines(TAIL_LINES * 2));
+        capture.drain().await;
+        assert_eq!(capture.take_pending().len(), TAIL_LINES * 2);
+        assert!(capture.take_pending().is_empty());
+    }
+}
@@ -1,12 +1,13 @@
-use native_runner::{CommandRunner, TokioRunner};
+use native_runner::TokioRunner;
 use serde::Deserialize;
 use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::ffi::{OsStr, OsString};
 use std::fs;
 use std::path::{Path, PathBuf};
 use std::pin::Pin;
+use std::sync::{Arc, Mutex};
 use std::time::Instant;
-use tokio::process::Command;
 use tokio::{
     io::{self, AsyncBufReadExt},
     time,
@@ -21,10 +22,25 @@ use anyhow::{anyhow, bail, Context};
 use itertools::Itertools;
 use once_cell::sync::{Lazy, OnceCell};
 use structopt::StructOpt;
+use strum::VariantNames as _;
 
 mod api;
+mod api_server;
+mod builtin;
+mod bundle;
+mod command;
 mod display;
+mod junit;
+mod logs;
+mod manifest;
+mod rpc;
 mod server;
+mod socket;
+mod sync;
+mod telemetry;
+mod transcript;
+mod tune;
+mod vendor;
 
 #[cfg(feature = "jemalloc")]
 #[global_allocator]
@@ -37,24 +53,104 @@ static GLOBAL: mimalloc::MiMalloc = mimalloc::MiMalloc;
 #[tokio::main]
 async fn main() {
     env_logger::init();
-    let _sentry = option_env!("SENTRY_DSN").map(sentry::init);
+    let opt: Rumblebot = Rumblebot::from_args();
+    let config_path = match load_config() {
+        Ok(path) => path,
+        Err(err) => exit_with_error(err),
+    };
+    // only after the arguments and config are loaded, so that telemetry can be turned off
+    let telemetry = !opt.no_telemetry && config().telemetry();
+    let _sentry = if telemetry { telemetry::init() } else { None };
+
+    if let Err(err) = try_main(opt, &config_path).await {
+        exit_with_error(err);
+    }
+}
 
-    if let Err(err) = try_main().await {
-        eprintln!("ERROR: {}", err);
-        err.chain()
-            .skip(1)
-            .for_each(|cause| eprintln!("because: {}", cause));
-        std::process::exit(1);
+fn exit_with_error(err: anyhow::Error) -> ! {
+    // whatever was reading the output has stopped, like `head` does, so there's nothing wrong
+    let broken_pipe = err.chain().any(|cause| {
+        cause
+            .downcast_ref::<std::io::Error>()
+            .map_or(false, |err| err.kind() == std::io::ErrorKind::BrokenPipe)
+    });
+    if broken_pipe {
+        std::process::exit(0);
     }
+    eprintln!("ERROR: {}", err);
+    err.chain()
+        .skip(1)
+        .for_each(|cause| eprintln!("because: {}", cause));
+    let code = err
+        .downcast_ref::<ExitStatus>()
+        .map_or(exit_code::FAILURE, |status| status.code);
+    std::process::exit(code);
 }
 
 #[derive(StructOpt)]
 #[structopt(name = "Robot Runner CLI", author, setting = clap::AppSettings::DeriveDisplayOrder)]
-enum Rumblebot {
+struct Rumblebot {
+    /// When to color the output: "auto", "always", or "never". "auto" respects the `NO_COLOR`
+    /// environment variable, and doesn't color output that isn't going to a terminal.
+    #[structopt(long, global = true)]
+    color: Option<display::ColorMode>,
+    /// Only use plain ASCII characters for drawing the board
+    #[structopt(long, global = true)]
+    ascii: bool,
+    /// Don't send crash reports. They can also be turned off for good with `telemetry = false` in
+    /// the config file
+    #[structopt(long, global = true)]
+    no_telemetry: bool,
+    #[structopt(subcommand)]
+    cmd: Subcommand,
+}
+
+#[derive(StructOpt)]
+#[structopt(setting = clap::AppSettings::DeriveDisplayOrder)]
+enum Subcommand {
     /// Commands for running battles locally
     Run(Run),
     /// Commands for interacting with robotrumble.org
     Account(Account),
+    /// Read and change rumblebot's settings
+    ///
+    /// The settings are `auth_key`, `base_url` (the robotrumble.org server to use), and `telemetry`
+    /// (whether to send crash reports). `auth_key` and `base_url` can be overridden with the
+    /// `RUMBLEBOT_AUTH_KEY` and `RUMBLEBOT_BASE_URL` environment variables, which aren't saved.
+    Config(ConfigCommand),
+    /// Speak JSON-RPC 2.0 over stdin and stdout, one message per line, for editor integrations
+    ///
+    /// Methods:
+    ///   runGame {"blue": ..., "red": ..., "seed": ..., "turn_num": ..., "game_mode": ...}
+    ///     Responds with the full output of the battle. After every turn, a `progress`
+    ///     notification is sent with `{"id": $REQUEST_ID, "turn": ...}`.
+    ///   runTurn {"robot": ..., "input": $PROGRAM_INPUT}
+    ///     Runs a single turn of a robot and responds with its output.
+    ///   checkRobot {"robot": ...}
+    ///     Responds with `{"ok": true}` if the robot initializes, or `{"ok": false, "error": ...}`.
+    ///   cancel {"id": $REQUEST_ID}
+    ///     Stops a running request, which then responds with an error.
+    ///
+    /// For instructions on how to specify robots, see the help page for `run`.
+    #[structopt(verbatim_doc_comment)]
+    Rpc {
+        #[structopt(flatten)]
+        limits: command::CommandLimits,
+    },
+    /// Check that robots compile and initialize, without running a battle
+    ///
+    /// Exits with status 3 if any of the robots failed to initialize.
+    /// For instructions on how to specify robots, see the help page for `run`.
+    Check {
+        #[structopt(parse(from_os_str), required = true, min_values = 1)]
+        robots: Vec<OsString>,
+        /// How to print the results: "human", or "json" for an array of
+        /// `{"robot", "ok", "diagnostics": [{"file", "line", "column", "message", "details"}]}`
+        #[structopt(long, default_value = "human")]
+        format: CheckFormat,
+        #[structopt(flatten)]
+        limits: command::CommandLimits,
+    },
 }
 
 #[derive(StructOpt)]
@@ -65,12 +161,26 @@ enum Run {
     /// A robot is specified in one of the following ways:
     /// 1. `$USER/$ROBOT`. A robot published on robotrumble.org
     /// 2. `$PATH`. A path to a local file with robot code. It must have a file extension for one of the supported languages.
+    ///     It can also be a directory, which is made available to the robot read-only under `/source`. Its `robot.toml`
+    ///     can set the `entry` file to run (by default the first of `main.py`, `main.js`, `index.js`, `robot.py`, and
+    ///     `robot.js` that exists) and the `lang`.
+    ///     Third-party packages can be put in its `vendor` directory, either as is or as `.whl`/`.tgz` files, or listed
+    ///     as paths to `.whl`/`.tgz` files in the `vendor` list in its `robot.toml`. They're available read-only
+    ///     under `/vendor`, which is on the `PYTHONPATH` and `NODE_PATH`. Packages with native extensions aren't supported.
+    ///     Its `robot.toml` can also have an `env` table of environment variables to set for the robot.
     /// 3. `inline:$LANG;$CODE`. Pass the language and code directly as an argument.
     /// 4. `command:$PATH` or `localrunner:$PATH`. The path to a native binary or wasm file, respectively. Criteria:
     ///     After initialization, it must print a `Result<(), ProgramError>` in serde_json format and a newline.
     ///     It will then start receiving newline-delimited `ProgramInput` json object. It must respond to
     ///     each one with a `ProgramOutput` json object followed by a newline. The match is over when stdin is closed, and
     ///     the process may be forcefully terminated after that.
+    /// 5. `ghost:$PATH`. Replay what a robot answered each turn, from a transcript written by `--record-io`.
+    ///     On turns that aren't in the transcript, it does nothing.
+    /// 6. `builtin:$NAME`. One of the reference opponents built into rumblebot, which start up instantly:
+    ///     `idle` (does nothing), `random` (moves and attacks at random), `rush` (charges the nearest enemy),
+    ///     or `turtle` (holds its position, attacking enemies that come next to it).
+    /// 7. `tcp:$HOST:$PORT` or `unix:$PATH`. A robot that's already running and listening on a socket, e.g. in a
+    ///     debugger or on another machine. It speaks the same protocol as `command:` robots over the connection.
     #[structopt(verbatim_doc_comment)]
     Term {
         #[structopt(parse(from_os_str))]
@@ -80,9 +190,13 @@ enum Run {
         /// The number of turns to run in the match
         #[structopt(short, long, default_value = "100")]
         turn_num: usize,
-        /// Avoid printing human-friendly info and just output JSON
+        /// Avoid printing human-friendly info and just output JSON. Same as `--format json`
         #[structopt(long)]
         raw: bool,
+        /// How to print the match: "human", "json" (the whole `MainOutput` once the game is over), or
+        /// "ndjson" (one JSON line per turn as it happens, followed by a summary line)
+        #[structopt(long, default_value = "human")]
+        format: OutputFormat,
         /// Show only the blue robot's logs
         #[structopt(long)]
         blue_logs_only: bool,
@@ -92,12 +206,56 @@ enum Run {
         /// Only show the results of the battle
         #[structopt(long)]
         results_only: bool,
+        /// Draw every cell of the board with the same width, marking each unit's team and the
+        /// action it takes that turn
+        #[structopt(long)]
+        detailed: bool,
+        /// Show each unit's id on the board. Implies `--detailed`
+        #[structopt(long)]
+        unit_ids: bool,
+        /// Redraw the board in place every turn instead of printing a new one, like a movie
+        #[structopt(long)]
+        animate: bool,
+        /// How many turns per second to show with `--animate`
+        #[structopt(long, default_value = "10", requires = "animate")]
+        fps: f64,
         /// Choose the gamemode. Current supported: "Normal"
         #[structopt(long, parse(from_os_str))]
         game_mode: Option<OsString>,
         /// Specify a random seed for robot spawning. It can be of any length.
         #[structopt(long, parse(from_os_str))]
         seed: Option<OsString>,
+        /// Set an environment variable for the blue robot, as `KEY=VALUE`. Can be passed
+        /// multiple times, and overrides the `env` table in a robot directory's `robot.toml`
+        #[structopt(long, number_of_values = 1, parse(try_from_str = parse_env_var))]
+        blue_env: Vec<(String, String)>,
+        /// Set an environment variable for the red robot, like `--blue-env`
+        #[structopt(long, number_of_values = 1, parse(try_from_str = parse_env_var))]
+        red_env: Vec<(String, String)>,
+        /// Exit with status 2 unless the match ends with this result: "blue", "red", or "tie"
+        #[structopt(long)]
+        expect_winner: Option<Expectation>,
+        /// Exit with status 3 if either robot errored during the match
+        #[structopt(long)]
+        fail_on_robot_error: bool,
+        /// Write a JUnit XML report of the match to this file
+        #[structopt(long, parse(from_os_str))]
+        junit: Option<PathBuf>,
+        /// Write each robot's logs to `blue.log` and `red.log` in this directory, along with
+        /// anything the robot printed to stderr
+        #[structopt(long, parse(from_os_str))]
+        log_dir: Option<PathBuf>,
+        /// Write a transcript of everything each robot was sent and answered with to
+        /// `blue.ndjson` and `red.ndjson` in this directory, which can be replayed with `ghost:`
+        #[structopt(long, parse(from_os_str))]
+        record_io: Option<PathBuf>,
+        /// Write a `.tar.gz` to this file with the command line, rumblebot's version, the game's
+        /// spec and seed, how it ended, and the robots' logs, for attaching to a bug report. Your
+        /// auth key and local paths are scrubbed from it
+        #[structopt(long, parse(from_os_str))]
+        debug_bundle: Option<PathBuf>,
+        #[structopt(flatten)]
+        limits: command::CommandLimits,
     },
     /// Run a continuous series of games 
     ///
@@ -105,12 +263,86 @@ enum Run {
     /// this means that there is no need to initialize rumblebot from scratch for every game.
     /// Expects inputs of the form `{"red": "...", "blue": "...", "seed": "(optional)", "turn_num": (optional) }`.
     /// For each input, `batch` simulates the game, prints the winner, and then waits for the next
-    /// input. 
+    /// input. An input can also have an `"expect_winner"` of "blue", "red", or "tie"; once stdin is
+    /// closed, `batch` exits with the same status codes as `term` if any of those weren't met.
+    /// A game that can't be run at all prints an `"error"` instead, and makes `batch` exit with
+    /// status 1 once the rest of the games are done.
+    /// `"blue_env"` and `"red_env"` are objects of environment variables to set for each robot.
     ///
     /// For instructions on how to specify robots, see the help page for `run`.
     Batch {
         #[structopt(long, parse(from_os_str))]
         game_mode: Option<OsString>,
+        /// Exit with status 3 if a robot errored in any of the matches
+        #[structopt(long)]
+        fail_on_robot_error: bool,
+        /// Write a JUnit XML report with a test case for each match to this file
+        #[structopt(long, parse(from_os_str))]
+        junit: Option<PathBuf>,
+        #[structopt(flatten)]
+        limits: command::CommandLimits,
+    },
+    /// Search for the best values of the parameters a robot reads from its environment variables
+    ///
+    /// The parameter space is a TOML file with a table for each environment variable, with either
+    /// `min`/`max` (and an optional `step` for grid search) or a list of `choices`:
+    ///
+    ///     [params.AGGRESSION]
+    ///     min = 0.0
+    ///     max = 1.0
+    ///     step = 0.25
+    ///
+    ///     [params.OPENING]
+    ///     choices = ["rush", "turtle"]
+    ///
+    /// Each set of parameters is scored by its win rate (counting ties as half a win) against every
+    /// opponent over several seeds, playing each seed from both sides.
+    ///
+    /// For instructions on how to specify robots, see the help page for `run`.
+    #[structopt(verbatim_doc_comment)]
+    Tune {
+        #[structopt(parse(from_os_str))]
+        robot: OsString,
+        /// The TOML file with the parameter space
+        #[structopt(long, parse(from_os_str))]
+        space: PathBuf,
+        /// A robot to play against. Can be passed multiple times
+        #[structopt(
+            long = "opponent",
+            parse(from_os_str),
+            required = true,
+            number_of_values = 1
+        )]
+        opponents: Vec<OsString>,
+        /// How to search the space: "grid", "random", or "evolve"
+        #[structopt(long, default_value = "random")]
+        strategy: tune::Strategy,
+        /// How many games to play against each opponent. Each seed is played twice, once from each
+        /// side, so this should be even
+        #[structopt(long, default_value = "4")]
+        seeds: usize,
+        /// The most parameter sets to try with the random and evolve strategies
+        #[structopt(long, default_value = "20")]
+        budget: usize,
+        /// How many parameter sets are in each generation of the evolve strategy
+        #[structopt(long, default_value = "8")]
+        population: usize,
+        /// Seed the search's random number generator, to make it reproducible
+        #[structopt(long)]
+        rng_seed: Option<u64>,
+        /// The number of turns to run in each match
+        #[structopt(short, long, default_value = "100")]
+        turn_num: usize,
+        #[structopt(long, parse(from_os_str))]
+        game_mode: Option<OsString>,
+        /// Write the full leaderboard as JSON to this file, updated after every parameter set
+        #[structopt(long, parse(from_os_str))]
+        leaderboard: Option<PathBuf>,
+        /// How many of the best parameter sets to print at the end
+        #[structopt(long, default_value = "10")]
+        top: usize,
+        #[structopt(flatten)]
+        limits: command::CommandLimits,
     },
     /// Run a battle and show the results in the normal web display
     ///
@@ -126,34 +358,180 @@ enum Run {
         /// The network port to listen to.
         #[structopt(short, long, env = "PORT")]
         port: Option<u16>,
+        #[structopt(flatten)]
+        limits: command::CommandLimits,
+    },
+    /// Serve a JSON HTTP API for running battles, so other programs can drive rumblebot
+    ///
+    /// Battles are specified the same way as for `batch`, and can also have a `"game_mode"`.
+    /// `POST /run` runs the battle and responds with its full output once it's over.
+    /// `POST /jobs` starts the battle in the background and responds with `{"id": $ID}`.
+    /// `GET /jobs/$ID` then responds with `{"status": "running"}`, `{"status": "done", "output": ...}`,
+    /// or `{"status": "failed", "error": "..."}`. A finished job can only be fetched once, and is
+    /// dropped after an hour if it isn't fetched at all.
+    ///
+    /// Requests with a body must have `Content-Type: application/json`. `command:`,
+    /// `localrunner:`, `tcp:` and `unix:` robots are rejected unless `--allow-native` is passed,
+    /// and robots from files (a path or `ghost:`) unless they're under `--robot-root`. Once
+    /// `--max-games` games are running, battles are turned down with 429 Too Many Requests.
+    ///
+    /// For instructions on how to specify robots, see the help page for `run`.
+    #[structopt(verbatim_doc_comment)]
+    ServeApi {
+        /// The network address to listen to.
+        #[structopt(short, long, default_value = "127.0.0.1")]
+        address: String,
+        /// The network port to listen to.
+        #[structopt(short, long, env = "PORT", default_value = "5253")]
+        port: u16,
+        /// Require every request to have an `Authorization: Bearer $TOKEN` header
+        #[structopt(long, env = "RUMBLEBOT_SERVE_API_TOKEN", hide_env_values = true)]
+        token: Option<String>,
+        /// Allow robots that aren't sandboxed, like `command:`. Anyone who can reach the API can
+        /// then run any program as you, so only use this with `--token` or on a trusted network
+        #[structopt(long)]
+        allow_native: bool,
+        /// Allow robots from files (a path or `ghost:`) from under this directory. Without it,
+        /// only published, inline and builtin robots are allowed
+        #[structopt(long, parse(from_os_str))]
+        robot_root: Option<PathBuf>,
+        /// The most games to run at once, counting both `/run` and `/jobs`
+        #[structopt(long, default_value = "4")]
+        max_games: usize,
+        #[structopt(flatten)]
+        limits: command::CommandLimits,
     },
 }
 
+#[derive(StructOpt)]
+#[structopt(setting = clap::AppSettings::DeriveDisplayOrder)]
+enum ConfigCommand {
+    /// Print the path of the config file
+    Path,
+    /// Print the value of a setting, including any override from the environment
+    Get {
+        #[structopt(possible_values = ConfigKey::VARIANTS)]
+        key: ConfigKey,
+    },
+    /// Change a setting
+    Set {
+        #[structopt(possible_values = ConfigKey::VARIANTS)]
+        key: ConfigKey,
+        value: String,
+    },
+    /// Reset a setting to its default
+    Unset {
+        #[structopt(possible_values = ConfigKey::VARIANTS)]
+        key: ConfigKey,
+    },
+    /// Print all of the settings
+    List,
+}
+
+#[derive(Clone, Copy, strum::EnumString, strum::VariantNames, strum::AsRefStr, strum::EnumIter)]
+#[strum(serialize_all = "snake_case")]
+enum ConfigKey {
+    AuthKey,
+    BaseUrl,
+    Telemetry,
+}
+
+impl ConfigKey {
+    /// The environment variable that overrides the setting, if there is one
+    fn env_var(self) -> Option<&'static str> {
+        match self {
+            Self::AuthKey => Some("RUMBLEBOT_AUTH_KEY"),
+            Self::BaseUrl => Some("RUMBLEBOT_BASE_URL"),
+            Self::Telemetry => None,
+        }
+    }
+
+    fn env_override(self) -> Option<String> {
+        let value = std::env::var(self.env_var()?).ok()?;
+        Some(value).filter(|value| !value.is_empty())
+    }
+}
+
 #[derive(StructOpt)]
 #[structopt(setting = clap::AppSettings::DeriveDisplayOrder)]
 enum Account {
     /// Login to robotrumble.org. This allows you to use the rumblebot account commands
     Login {
         username: String,
-        #[structopt(short)]
+        /// Your password. Note that it ends up in your shell history and is visible to other
+        /// processes; `--password-stdin` is safer
+        #[structopt(short, conflicts_with = "password-stdin")]
         password: Option<String>,
+        /// Read the password from the first line of stdin, e.g. for CI. An API token in the
+        /// `RUMBLEBOT_API_TOKEN` environment variable (or a file named by
+        /// `RUMBLEBOT_API_TOKEN_FILE`) can be used instead of logging in at all
+        #[structopt(long)]
+        password_stdin: bool,
     },
     Logout {},
-    /// Create a new robot. By default, `name` and `lang` are inferred from the file path
+    /// Create a new robot. By default, `name` and `lang` are inferred from the file path, or from
+    /// the manifest of a robot directory
     Create {
+        /// A robot file or directory. Directories are always bundled
         #[structopt(parse(from_os_str))]
         file: PathBuf,
         #[structopt(long, short)]
         name: Option<String>,
         #[structopt(long, short)]
         lang: Option<Lang>,
+        /// Bundle the local modules the robot imports into the uploaded code
+        #[structopt(long)]
+        bundle: bool,
     },
-    /// Update a robot's code. By default, `name` is inferred from the file path
+    /// Update a robot's code. By default, `name` is inferred from the file path, or from the
+    /// manifest of a robot directory
     Update {
+        /// A robot file or directory. Directories are always bundled
         #[structopt(parse(from_os_str))]
         file: PathBuf,
         #[structopt(long, short)]
         name: Option<String>,
+        /// Bundle the local modules the robot imports into the uploaded code
+        #[structopt(long)]
+        bundle: bool,
+    },
+    /// Show how a robot's local code differs from what's published. Exits with status 4 if
+    /// there are any differences, so that scripts can check for unpublished changes. By default,
+    /// `name` is inferred from the file path, or from the manifest of a robot directory
+    Diff {
+        /// A robot file or directory. Directories are always bundled
+        #[structopt(parse(from_os_str))]
+        file: PathBuf,
+        #[structopt(long, short)]
+        name: Option<String>,
+        /// Bundle the local modules the robot imports, like `update --bundle` would
+        #[structopt(long)]
+        bundle: bool,
+    },
+    /// Sync a directory of robots with your robots on robotrumble.org. Robot files and directories
+    /// are matched to robots by name, or the name in their manifest. Robots that only exist
+    /// locally are created, ones that only exist on the site are downloaded, and whichever side
+    /// changed since the last sync is updated. Robots that changed on both sides are left alone
+    Sync {
+        #[structopt(parse(from_os_str))]
+        dir: PathBuf,
+        /// Overwrite robots on the site with the local code even if they changed there since the
+        /// last sync
+        #[structopt(long)]
+        force: bool,
+        /// Bundle the local modules that robot files import. Directories are always bundled
+        #[structopt(long)]
+        bundle: bool,
+    },
+    /// List a user's robots, with their language, id, whether they're published, and their rating
+    List {
+        /// Defaults to you
+        user: Option<String>,
+    },
+    /// Show the details of a robot
+    Info {
+        /// Should take the form `$USER/$ROBOT`, or just `$ROBOT` for one of your own
+        slug: String,
     },
     /// Download any published robot from robotrumble.org
     Download {
@@ -163,43 +541,117 @@ enum Account {
     },
 }
 
-fn make_sourcedir(f: impl AsRef<Path>) -> anyhow::Result<tempfile::TempDir> {
+/// The directory that's preopened as `/source` for a wasm robot, and the file in it to run
+struct SourceDir {
+    path: PathBuf,
+    /// The path of the file to run, relative to `/source`
+    entry: String,
+    /// If the source is a single file, it's put in a temporary directory that we need to keep open
+    _tempdir: Option<tempfile::TempDir>,
+    /// Third-party packages, preopened as `/vendor`
+    vendor: Option<vendor::VendorDir>,
+    /// Environment variables from the robot directory's manifest
+    env: BTreeMap<String, String>,
+}
+
+impl SourceDir {
+    fn temp(tempdir: tempfile::TempDir) -> Self {
+        Self {
+            path: tempdir.path().to_owned(),
+            entry: "sourcecode".to_owned(),
+            _tempdir: Some(tempdir),
+            vendor: None,
+            env: BTreeMap::new(),
+        }
+    }
+}
+
+fn make_sourcedir(f: impl AsRef<Path>) -> anyhow::Result<SourceDir> {
     let f = f.as_ref();
+    if f.is_dir() {
+        // the whole directory is exposed to the robot, read-only
+        let robot = manifest::RobotDir::load(f)?;
+        return Ok(SourceDir {
+            path: f.to_owned(),
+            entry: robot.wasi_entry(),
+            _tempdir: None,
+            vendor: vendor::prepare(f, &robot.manifest)?,
+            env: robot.manifest.env,
+        });
+    }
     let sourcedir = tempfile::tempdir().context("couldn't create temporary directory")?;
     let sourcecode_path = sourcedir.path().join("sourcecode");
     fs::hard_link(f, &sourcecode_path)
         .or_else(|_| fs::copy(f, sourcecode_path).map(drop))
         .context("couldn't copy file to tempdir")?;
-    Ok(sourcedir)
+    Ok(SourceDir::temp(sourcedir))
 }
-fn make_sourcedir_inline(source: &str) -> anyhow::Result<tempfile::TempDir> {
+fn make_sourcedir_inline(source: &str) -> anyhow::Result<SourceDir> {
     let sourcedir = tempfile::tempdir().context("couldn't create temporary directory")?;
     fs::write(sourcedir.path().join("sourcecode"), source)
         .context("Couldn't write code to disk")?;
-    Ok(sourcedir)
+    Ok(SourceDir::temp(sourcedir))
 }
 
 type WasiRunner =
     TokioRunner<io::BufWriter<wasi_process2::WasiStdin>, io::BufReader<wasi_process2::WasiStdout>>;
 
 enum RunnerKind {
-    Command(CommandRunner),
+    Command(command::CommandRobot),
     Wasi {
         runner: WasiRunner,
         /// the directory that we store the source file in; we need to keep it open
-        _dir: tempfile::TempDir,
+        _dir: SourceDir,
         memory: wasmer::Memory,
     },
+    Ghost(transcript::Ghost),
+    Builtin(builtin::Builtin),
+    Socket(socket::SocketRunner),
 }
 
 pub struct Runner {
     kind: RunnerKind,
     timeout: Option<(Pin<Box<time::Sleep>>, time::Duration)>,
+    recorder: Option<transcript::Recorder>,
+    error_turn: Option<ErrorTurn>,
+}
+
+/// The turn that a robot errored on, if it has. The game only reports the error itself
+#[derive(Clone, Default)]
+pub struct ErrorTurn(Arc<Mutex<Option<usize>>>);
+
+impl ErrorTurn {
+    pub fn get(&self) -> Option<usize> {
+        *self.0.lock().unwrap()
+    }
+}
+
+/// Settings for running a robot that apply no matter what kind of robot it is
+#[derive(Clone, Default)]
+pub struct RunnerOptions {
+    /// Where a wasm robot's stderr goes. If this isn't set, it's forwarded straight to our stderr
+    pub stderr: Option<logs::StderrCapture>,
+    /// Environment variables to set for the robot, on top of (and overriding) the ones in its
+    /// manifest
+    pub env: BTreeMap<String, String>,
+    /// Where to write a transcript of everything the robot is sent and answers with
+    pub record_io: Option<PathBuf>,
+    pub limits: command::CommandLimits,
+    /// What builtin robots seed their random choices with, so that a game with a seed plays out
+    /// the same every time. If this isn't set, a random one is picked
+    pub seed: Option<String>,
+    /// Where to keep the turn the robot errored on
+    pub error_turn: Option<ErrorTurn>,
 }
 
 #[async_trait::async_trait]
 impl RobotRunner for Runner {
     async fn run(&mut self, input: logic::ProgramInput<'_>) -> logic::ProgramResult {
+        let turn = input.state.turn;
+        let recorded_input = self
+            .recorder
+            .as_ref()
+            .map(|_| serde_json::to_value(&input).unwrap());
         let kind = &mut self.kind;
         let inner = async move {
             match kind {
@@ -213,9 +665,12 @@ impl RobotRunner for Runner {
                     );
                     runner.run(input).await
                 }
+                RunnerKind::Ghost(ghost) => ghost.output(input.state.turn),
+                RunnerKind::Builtin(builtin) => builtin.run(input).await,
+                RunnerKind::Socket(r) => r.run(input).await,
             }
         };
-        match &mut self.timeout {
+        let result = match &mut self.timeout {
             Some((timeout, dur)) => {
                 tokio::select! {
                     res = inner => res,
@@ -223,7 +678,16 @@ impl RobotRunner for Runner {
                 }
             }
             None => inner.await,
+        };
+        if let (Some(recorder), Some(input)) = (&mut self.recorder, recorded_input) {
+            if let Err(err) = recorder.record(turn, &input, &result) {
+                log::warn!("couldn't write to the transcript: {}", err);
+            }
         }
+        if let (Err(_), Some(error_turn)) = (&result, &self.error_turn) {
+            error_turn.0.lock().unwrap().get_or_insert(turn);
+        }
+        result
     }
 }
 
@@ -238,15 +702,28 @@ impl Runner {
         module: &wasmer::Module,
         version: WasiVersion,
         args: &[String],
-        dir: tempfile::TempDir,
+        dir: SourceDir,
+        opts: &RunnerOptions,
     ) -> anyhow::Result<logic::ProgramResult<Self>> {
         let mut state = wasmer_wasi::WasiState::new("robot");
         wasi_process2::add_stdio(&mut state);
         state
-            .preopen(|p| p.directory(&dir).alias("source").read(true))
+            .preopen(|p| p.directory(&dir.path).alias("source").read(true))
             .unwrap()
             .args(args)
-            .arg("/source/sourcecode");
+            .arg(format!("/source/{}", dir.entry));
+        let mut env = BTreeMap::new();
+        if let Some(vendor) = &dir.vendor {
+            state
+                .preopen(|p| p.directory(&vendor.path).alias("vendor").read(true))
+                .unwrap();
+            for var in &["PYTHONPATH", "NODE_PATH"] {
+                env.insert(var.to_string(), vendor::WASI_VENDOR_DIR.to_owned());
+            }
+        }
+        env.extend(dir.env.clone());
+        env.extend(opts.env.clone());
+        state.envs(env);
         let env = wasmer_wasi::WasiEnv::new(state.build()?);
         let instance = {
             // imports isn't Send
@@ -259,10 +736,16 @@ impl Runner {
         let stdin = io::BufWriter::new(proc.stdin.take().unwrap());
         let stdout = io::BufReader::new(proc.stdout.take().unwrap());
 
-        // forward wasi stderr to io::stderr
-        let mut proc_stderr = io::BufReader::new(proc.stderr.take().unwrap());
-        let mut stderr = tokio::io::stderr();
-        tokio::spawn(async move { tokio::io::copy(&mut proc_stderr, &mut stderr).await });
+        let proc_stderr = proc.stderr.take().unwrap();
+        match &opts.stderr {
+            Some(capture) => capture.spawn_reader(proc_stderr),
+            None => {
+                // forward wasi stderr to io::stderr
+                let mut proc_stderr = io::BufReader::new(proc_stderr);
+                let mut stderr = tokio::io::stderr();
+                tokio::spawn(async move { tokio::io::copy(&mut proc_stderr, &mut stderr).await });
+            }
+        }
 
         proc.spawn();
 
@@ -273,11 +756,24 @@ impl Runner {
                 memory: memory.clone(),
             },
             timeout: None,
+            recorder: None,
+            error_turn: None,
         });
         Ok(program_result)
     }
-    async fn from_id(id: &RobotId) -> anyhow::Result<logic::ProgramResult<Self>> {
-        match id {
+    fn socket(runner: socket::SocketRunner) -> Self {
+        Self {
+            kind: RunnerKind::Socket(runner),
+            timeout: None,
+            recorder: None,
+            error_turn: None,
+        }
+    }
+    async fn from_id(
+        id: &RobotId,
+        opts: &RunnerOptions,
+    ) -> anyhow::Result<logic::ProgramResult<Self>> {
+        let mut result = match id {
             RobotId::Published { user, robot } => {
                 let info = api::robot_info(user, robot)
                     .await?
@@ -288,7 +784,7 @@ impl Runner {
                 let sourcedir = make_sourcedir_inline(&code)?;
                 let store = &*STORE;
                 let (module, version) = info.lang.get_wasm(store)?;
-                Runner::new_wasm(store, module, version, &[], sourcedir).await
+                Runner::new_wasm(store, module, version, &[], sourcedir, opts).await
             }
             RobotId::Local { source, lang } => {
                 let sourcedir = make_sourcedir(source)?;
@@ -296,14 +792,22 @@ impl Runner {
                 let (module, version) = lang.get_wasm(store)?;
                 // This is very strange, but this exactly println in this exact place is necessary to avoid
                 // error: "corrupted binary: misaligned metadata"
-                Runner::new_wasm(store, module, version, &[], sourcedir).await
+                Runner::new_wasm(store, module, version, &[], sourcedir, opts).await
             }
             RobotId::Command { command, args } => {
-                let mut cmd = Command::new(command);
-                cmd.args(args);
-                let program_result = TokioRunner::new_cmd(cmd).await.map(|r| Self {
+                let program_result = command::CommandRobot::spawn(
+                    command,
+                    args,
+                    &opts.env,
+                    &opts.limits,
+                    opts.stderr.as_ref(),
+                )
+                .await?
+                .map(|r| Self {
                     kind: RunnerKind::Command(r),
                     timeout: None,
+                    recorder: None,
+                    error_turn: None,
                 });
                 Ok(program_result)
             }
@@ -319,15 +823,41 @@ impl Runner {
                 let store = &*STORE;
                 let (module, version) = wasm_from_cache_or_compile(store, &wasm)
                     .with_context(|| format!("couldn't compile wasm module at {}", runner))?;
-                Runner::new_wasm(store, &module, version, &runner_args, sourcedir).await
+                Runner::new_wasm(store, &module, version, &runner_args, sourcedir, opts).await
             }
             RobotId::Inline { lang, source } => {
                 let sourcedir = make_sourcedir_inline(source)?;
                 let store = &*STORE;
                 let (module, version) = lang.get_wasm(store)?;
-                Runner::new_wasm(store, module, version, &[], sourcedir).await
+                Runner::new_wasm(store, module, version, &[], sourcedir, opts).await
+            }
+            RobotId::Ghost { transcript } => Ok(Ok(Self {
+                kind: RunnerKind::Ghost(transcript::Ghost::load(transcript)?),
+                timeout: None,
+                recorder: None,
+                error_turn: None,
+            })),
+            RobotId::Builtin { name } => {
+                let seed = opts.seed.clone().unwrap_or_else(telemetry::random_seed);
+                let builtin = builtin::Builtin::new(name, &seed)
+                    .ok_or_else(|| anyhow!("unknown builtin robot {:?}", name))?;
+                Ok(Ok(Self {
+                    kind: RunnerKind::Builtin(builtin),
+                    timeout: None,
+                    recorder: None,
+                    error_turn: None,
+                }))
             }
+            RobotId::Tcp { address } => Ok(socket::connect_tcp(address).await.map(Self::socket)),
+            RobotId::Unix { path } => Ok(socket::connect_unix(path).await.map(Self::socket)),
+        }?;
+        if let (Ok(runner), Some(path)) = (&mut result, &opts.record_io) {
+            runner.recorder = Some(transcript::Recorder::create(path)?);
         }
+        if let Ok(runner) = &mut result {
+            runner.error_turn = opts.error_turn.clone();
+        }
+        Ok(result)
     }
 }
 
@@ -342,10 +872,66 @@ const PROD_BASE_URL: &str = "https://robotrumble.org";
 struct Config {
     auth_key: Option<String>,
     base_url: Option<Cow<'static, str>>,
+    /// Whether to send crash reports. Defaults to true
+    telemetry: Option<bool>,
 }
 impl Config {
-    fn base_url(&self) -> &str {
-        self.base_url.as_deref().unwrap_or(PROD_BASE_URL)
+    fn base_url(&self) -> Cow<'_, str> {
+        match ConfigKey::BaseUrl.env_override() {
+            Some(url) => url.into(),
+            None => self.base_url.as_deref().unwrap_or(PROD_BASE_URL).into(),
+        }
+    }
+    fn auth_key(&self) -> Option<Cow<'_, str>> {
+        match ConfigKey::AuthKey.env_override() {
+            Some(key) => Some(key.into()),
+            None => self.auth_key.as_deref().map(Cow::from),
+        }
+    }
+    fn telemetry(&self) -> bool {
+        self.telemetry.unwrap_or(true)
+    }
+    /// The value of a setting, with any override from the environment
+    fn get(&self, key: ConfigKey) -> Option<String> {
+        match key {
+            ConfigKey::AuthKey => self.auth_key().map(Cow::into_owned),
+            ConfigKey::BaseUrl => Some(self.base_url().into_owned()),
+            ConfigKey::Telemetry => Some(self.telemetry().to_string()),
+        }
+    }
+    /// Change a setting, or reset it to its default with `None`
+    fn set(&mut self, key: ConfigKey, value: Option<&str>) -> anyhow::Result<()> {
+        match key {
+            ConfigKey::AuthKey => {
+                if value.map_or(false, |key| key.trim().is_empty()) {
+                    bail!("auth_key can't be empty");
+                }
+                self.auth_key = value.map(str::to_owned);
+            }
+            ConfigKey::BaseUrl => {
+                if let Some(url) = value {
+                    let parsed = reqwest::Url::parse(url)
+                        .with_context(|| format!("base_url {:?} isn't a valid URL", url))?;
+                    if !["http", "https"].contains(&parsed.scheme()) || parsed.cannot_be_a_base() {
+                        bail!(
+                            "base_url must be an http or https URL, like {}",
+                            PROD_BASE_URL
+                        );
+                    }
+                }
+                self.base_url = value.map(|url| url.to_owned().into());
+            }
+            ConfigKey::Telemetry => {
+                self.telemetry = value
+                    .map(|value| {
+                        value
+                            .parse()
+                            .map_err(|_| anyhow!("telemetry must be true or false"))
+                    })
+                    .transpose()?;
+            }
+        }
+        Ok(())
     }
 }
 
@@ -360,8 +946,8 @@ fn store_config(path: &Path, c: &Config) -> anyhow::Result<()> {
     Ok(())
 }
 
-async fn try_main() -> anyhow::Result<()> {
-    let opt: Rumblebot = Rumblebot::from_args();
+/// Load the config into `CONFIG`, returning its path
+fn load_config() -> anyhow::Result<PathBuf> {
     let config_dir = directories()?.config_dir();
     let config_path = config_dir.join("config.toml");
     CONFIG
@@ -374,53 +960,262 @@ async fn try_main() -> anyhow::Result<()> {
             Err(e) => Err(e.into()),
         })
         .context("Unable to load config")?;
+    Ok(config_path)
+}
+
+async fn try_main(opt: Rumblebot, config_path: &Path) -> anyhow::Result<()> {
+    // only the account commands use the token, so a bad one doesn't get in the way of anything else
+    if let Subcommand::Account(_) = opt.cmd {
+        api::api_token().context("Unable to load the API token")?;
+    }
+    display::set_style(opt.color.unwrap_or(display::ColorMode::Auto), opt.ascii);
 
-    match opt {
-        Rumblebot::Run(run_opt) => match run_opt {
+    match opt.cmd {
+        Subcommand::Run(run_opt) => match run_opt {
             Run::Term {
                 bluebot,
                 redbot,
                 turn_num,
                 raw,
+                format,
                 blue_logs_only,
                 red_logs_only,
                 results_only,
+                detailed,
+                unit_ids,
+                animate,
+                fps,
                 game_mode: game_mode_string,
                 seed,
+                blue_env,
+                red_env,
+                expect_winner,
+                fail_on_robot_error,
+                junit,
+                log_dir,
+                record_io,
+                debug_bundle,
+                limits,
             } => {
                 let game_mode = parse_game_mode(game_mode_string);
-                let output = run_game(
-                    GameSpec {
-                        red: redbot.to_string_lossy().to_string(),
-                        blue: bluebot.to_string_lossy().to_string(),
-                        seed: seed.map(|k| k.to_string_lossy().to_string()),
-                        turn_num: Some(turn_num)
-                    },
-                    game_mode,
-                    !raw && !results_only,
-                    red_logs_only,
-                    blue_logs_only,
-                )
-                .await?;
-                if raw {
-                    let stdout = std::io::stdout();
-                    serde_json::to_writer(stdout.lock(), &output).unwrap();
+                let format = if raw { OutputFormat::Json } else { format };
+                let display_turns = format == OutputFormat::Human && !results_only;
+                if animate && !(fps.is_finite() && fps > 0.0) {
+                    bail!("--fps must be a positive number");
+                }
+                let board = display::BoardOptions {
+                    detailed: detailed || unit_ids,
+                    unit_ids,
+                };
+                let (mut animation, show_frames) = if animate {
+                    let (animation, show_frames) = display::Animation::new(fps, board);
+                    (Some(animation), Some(show_frames))
                 } else {
-                    if !results_only {
-                        println!("");
+                    (None, None)
+                };
+                let spec = GameSpec {
+                    red: redbot.to_string_lossy().to_string(),
+                    blue: bluebot.to_string_lossy().to_string(),
+                    // pick the seed ourselves for a debug bundle, so that the game can be replayed
+                    seed: seed
+                        .map(|k| k.to_string_lossy().to_string())
+                        .or_else(|| debug_bundle.as_ref().map(|_| telemetry::random_seed())),
+                    turn_num: Some(turn_num),
+                    expect_winner,
+                    game_mode: Some(game_mode),
+                    blue_env: blue_env.into_iter().collect(),
+                    red_env: red_env.into_iter().collect(),
+                };
+                let case_name = spec.display_name();
+                let spec_json = spec.to_json();
+                // a debug bundle needs the logs, even if they weren't asked for
+                let bundle_logs = match (&log_dir, &debug_bundle) {
+                    (None, Some(_)) => {
+                        Some(tempfile::tempdir().context("couldn't create temporary directory")?)
+                    }
+                    _ => None,
+                };
+                let log_dir =
+                    log_dir.or_else(|| bundle_logs.as_ref().map(|dir| dir.path().to_owned()));
+                let start = Instant::now();
+                let opts = RunOptions {
+                    log_dir,
+                    record_io,
+                    limits,
+                    error_turns: maplit::btreemap! {
+                        logic::Team::Blue => ErrorTurn::default(),
+                        logic::Team::Red => ErrorTurn::default(),
+                    },
+                };
+                let game = async {
+                    // once printing fails (e.g. the output was piped into `head`), the rest of the
+                    // turns aren't printed, and the error is returned after the game
+                    let mut printed = Ok(());
+                    let output = run_game(spec, game_mode, &opts, |turn_state| {
+                        if printed.is_err() {
+                            return;
+                        }
+                        printed = match format {
+                            OutputFormat::Human if display_turns => match &mut animation {
+                                Some(animation) => {
+                                    animation.draw(turn_state, !red_logs_only, !blue_logs_only)
+                                }
+                                None => display::display_turn(
+                                    turn_state,
+                                    board,
+                                    !red_logs_only,
+                                    !blue_logs_only,
+                                ),
+                            },
+                            OutputFormat::Ndjson => write_ndjson_line("turn", turn_state),
+                            _ => Ok(()),
+                        };
+                    })
+                    .await;
+                    // that was the last frame, so showing them finishes once it's caught up
+                    drop(animation);
+                    (output, printed)
+                };
+                let ((output, printed), shown) = tokio::join!(game, async {
+                    match show_frames {
+                        Some(show_frames) => show_frames.await,
+                        None => Ok(()),
+                    }
+                });
+                printed.context("Couldn't print the game")?;
+                shown.context("Couldn't print the game")?;
+                // the bundle is only a side effect, so failing to write it doesn't stop the JUnit
+                // report or change the exit status
+                if let Some(path) = &debug_bundle {
+                    match telemetry::write_debug_bundle(
+                        path,
+                        &spec_json,
+                        output.as_ref(),
+                        opts.log_dir.as_deref(),
+                    ) {
+                        Ok(()) => eprintln!("Wrote debug bundle to {}", path.display()),
+                        Err(err) => eprintln!("Couldn't write debug bundle: {:#}", err),
                     }
-                    display::display_output(output)?;
                 }
+                let output = match output {
+                    Ok(output) => output,
+                    Err(err) => {
+                        if let Some(junit) = junit {
+                            let case = junit::TestCase {
+                                name: case_name,
+                                time: start.elapsed(),
+                                outcome: junit::Outcome::Errored(format!("{:#}", err)),
+                            };
+                            junit::write_report(&junit, &[case])?;
+                        }
+                        return Err(err);
+                    }
+                };
+                let status = check_match(&output, expect_winner, fail_on_robot_error);
+                if let Some(junit) = junit {
+                    let case = junit::TestCase {
+                        name: case_name,
+                        time: start.elapsed(),
+                        outcome: junit::Outcome::from_status(&status),
+                    };
+                    junit::write_report(&junit, &[case])?;
+                }
+                match format {
+                    OutputFormat::Human => {
+                        if !results_only {
+                            println!("");
+                        }
+                        let sources = maplit::btreemap! {
+                            logic::Team::Blue => &bluebot,
+                            logic::Team::Red => &redbot,
+                        }
+                        .into_iter()
+                        .filter_map(|(team, id)| {
+                            let source = RobotId::parse(id).ok()?.source_file()?;
+                            Some((team, source))
+                        })
+                        .collect();
+                        let error_turns = opts
+                            .error_turns
+                            .iter()
+                            .filter_map(|(&team, turn)| Some((team, turn.get()?)))
+                            .collect();
+                        display::display_output(output, &sources, &error_turns)?;
+                    }
+                    OutputFormat::Json => {
+                        let stdout = std::io::stdout();
+                        serde_json::to_writer(stdout.lock(), &output)
+                            .map_err(std::io::Error::from)?;
+                    }
+                    OutputFormat::Ndjson => {
+                        let summary = serde_json::json!({
+                            "winner": output.winner,
+                            "errors": output.errors,
+                            "turns": output.turns.len(),
+                        });
+                        write_ndjson_line("summary", &summary)?;
+                    }
+                }
+                status?;
             }
             Run::Batch {
                 game_mode: game_mode_string,
+                fail_on_robot_error,
+                junit,
+                limits,
             } => {
                 let game_mode = parse_game_mode(game_mode_string);
+                let opts = RunOptions {
+                    limits,
+                    ..Default::default()
+                };
+                let mut cases = Vec::new();
+                let mut worst_status = Ok(());
+                // games that couldn't be run at all, which don't stop the rest of the batch
+                let mut run_errors = 0;
                 let mut stdin = io::BufReader::new(io::stdin()).lines();
                 while let Some(line) = stdin.next_line().await.unwrap() {
-                    match serde_json::from_str(&line) {
+                    match serde_json::from_str::<GameSpec>(&line) {
                         Ok(game_spec) => {
-                            let out = run_game(game_spec, game_mode, false, false, false).await?;
+                            let case_name = game_spec.display_name();
+                            let expect_winner = game_spec.expect_winner;
+                            let start = Instant::now();
+                            let out = match run_game(game_spec, game_mode, &opts, |_| {}).await {
+                                Ok(out) => out,
+                                Err(err) => {
+                                    let msg = format!("{:#}", err);
+                                    eprintln!("Couldn't run {}: {}", case_name, msg);
+                                    println!("{}", serde_json::json!({ "error": msg }));
+                                    if junit.is_some() {
+                                        cases.push(junit::TestCase {
+                                            name: case_name,
+                                            time: start.elapsed(),
+                                            outcome: junit::Outcome::Errored(msg),
+                                        });
+                                    }
+                                    run_errors += 1;
+                                    continue;
+                                }
+                            };
+
+                            let status = check_match(&out, expect_winner, fail_on_robot_error);
+                            if junit.is_some() {
+                                cases.push(junit::TestCase {
+                                    name: case_name,
+                                    time: start.elapsed(),
+                                    outcome: junit::Outcome::from_status(&status),
+                                });
+                            }
+                            if let Err(status) = status {
+                                eprintln!("{}", status);
+                                let worse = match &worst_status {
+                                    Ok(()) => true,
+                                    Err(worst) => status.code > worst.code,
+                                };
+                                if worse {
+                                    worst_status = Err(status);
+                                }
+                            }
 
                             let mut value = serde_json::to_value(&out).unwrap();
                             if let serde_json::Value::Object(v) = &mut value {
@@ -433,30 +1228,210 @@ async fn try_main() -> anyhow::Result<()> {
                         }
                     }
                 }
+                if let Some(junit) = junit {
+                    junit::write_report(&junit, &cases)?;
+                }
+                if run_errors > 0 {
+                    bail!("{} of the games couldn't be run", run_errors);
+                }
+                worst_status?;
+            }
+            Run::Tune {
+                robot,
+                space,
+                opponents,
+                strategy,
+                seeds,
+                budget,
+                population,
+                rng_seed,
+                turn_num,
+                game_mode: game_mode_string,
+                leaderboard,
+                top,
+                limits,
+            } => {
+                let space = tune::Space::load(&space)?;
+                let opts = tune::TuneOptions {
+                    robot: robot.to_string_lossy().to_string(),
+                    opponents: opponents
+                        .iter()
+                        .map(|id| id.to_string_lossy().to_string())
+                        .collect(),
+                    seeds,
+                    strategy,
+                    budget,
+                    population,
+                    turn_num,
+                    game_mode: parse_game_mode(game_mode_string),
+                    rng_seed,
+                    leaderboard,
+                    limits,
+                };
+                let entries = tune::tune(&space, &opts).await?;
+                display::display_leaderboard(&entries, top)?;
             }
             Run::Web {
                 robots,
                 address,
                 port,
+                limits,
             } => {
                 let ids = robots
                     .iter()
                     .map(|id| RobotId::parse(id))
                     .collect::<Result<Vec<_>, _>>()?;
-                server::serve(ids, address, port).await?;
+                server::serve(ids, address, port, limits).await?;
+            }
+            Run::ServeApi {
+                address,
+                port,
+                token,
+                allow_native,
+                robot_root,
+                max_games,
+                limits,
+            } => {
+                let opts = api_server::ServeOptions {
+                    token,
+                    allow_native,
+                    robot_root,
+                    max_games,
+                    limits,
+                };
+                api_server::serve(address, port, opts).await?;
             }
         },
 
-        Rumblebot::Account(account_opt) => match account_opt {
-            Account::Login { username, password } => {
+        Subcommand::Rpc { limits } => rpc::serve(limits).await?,
+
+        Subcommand::Config(cmd) => match cmd {
+            ConfigCommand::Path => println!("{}", config_path.display()),
+            ConfigCommand::Get { key } => match config().get(key) {
+                Some(value) => println!("{}", value),
+                None => bail!("{} isn't set", key.as_ref()),
+            },
+            ConfigCommand::Set { key, value } => {
+                let mut new_config = config().clone();
+                new_config.set(key, Some(&value))?;
+                store_config(config_path, &new_config).context("Error storing configuration")?;
+                if let (Some(var), Some(_)) = (key.env_var(), key.env_override()) {
+                    eprintln!(
+                        "Note: {} is set, which overrides {} until it's unset",
+                        var,
+                        key.as_ref()
+                    );
+                }
+            }
+            ConfigCommand::Unset { key } => {
+                let mut new_config = config().clone();
+                new_config.set(key, None)?;
+                store_config(config_path, &new_config).context("Error storing configuration")?;
+            }
+            ConfigCommand::List => {
+                for key in <ConfigKey as strum::IntoEnumIterator>::iter() {
+                    let value = match (key, config().get(key)) {
+                        (_, None) => "(not set)".to_owned(),
+                        // don't print secrets unless they're asked for by name
+                        (ConfigKey::AuthKey, Some(_)) => "(set)".to_owned(),
+                        (_, Some(value)) => value,
+                    };
+                    match key.env_var().filter(|_| key.env_override().is_some()) {
+                        Some(var) => println!("{} = {} (from {})", key.as_ref(), value, var),
+                        None => println!("{} = {}", key.as_ref(), value),
+                    }
+                }
+            }
+        },
+
+        Subcommand::Check {
+            robots,
+            format,
+            limits,
+        } => {
+            let ids = robots
+                .iter()
+                .map(|id| RobotId::parse(id))
+                .collect::<Result<Vec<_>, _>>()?;
+            let limits = &limits;
+            // a robot that couldn't be checked at all (e.g. it couldn't be downloaded) is
+            // reported as failed like the rest, rather than throwing away the other results
+            let results = futures_util::future::join_all(ids.iter().map(|id| async move {
+                let start = Instant::now();
+                let result = check_robot(id, limits)
+                    .await
+                    .unwrap_or_else(|err| Err(logic::ProgramError::IO(format!("{:#}", err))));
+                (result, start.elapsed())
+            }))
+            .await;
+
+            let failed = results.iter().filter(|(result, _)| result.is_err()).count();
+            match format {
+                CheckFormat::Human => {
+                    for (id, (result, time)) in ids.iter().zip(&results) {
+                        display::display_check(
+                            &id.display_name(),
+                            result,
+                            *time,
+                            id.source_file(),
+                        )?;
+                    }
+                }
+                CheckFormat::Json => {
+                    let report = ids
+                        .iter()
+                        .zip(&results)
+                        .map(|(id, (result, _))| {
+                            let file = id.source_name();
+                            let diagnostics = match result {
+                                Ok(()) => Vec::new(),
+                                Err(err) => vec![diagnostic(file.as_deref(), err)],
+                            };
+                            serde_json::json!({
+                                "robot": id.display_name(),
+                                "ok": result.is_ok(),
+                                "diagnostics": diagnostics,
+                            })
+                        })
+                        .collect_vec();
+                    println!("{}", serde_json::to_string_pretty(&report)?);
+                }
+            }
+            if failed > 0 {
+                return Err(ExitStatus {
+                    code: exit_code::ROBOT_ERROR,
+                    msg: format!(
+                        "{} of {} robots failed to initialize",
+                        failed,
+                        results.len()
+                    ),
+                }
+                .into());
+            }
+        }
+
+        Subcommand::Account(account_opt) => match account_opt {
+            Account::Login {
+                username,
+                password,
+                password_stdin,
+            } => {
                 let password = match password {
                     Some(pass) => pass,
-                    None => rpassword::read_password_from_tty(Some("Password: "))
-                        .context("Error reading password (try passing the -p option)")?,
+                    None if password_stdin => {
+                        let mut line = String::new();
+                        std::io::stdin()
+                            .read_line(&mut line)
+                            .context("Error reading password from stdin")?;
+                        line.trim_end_matches(&['\r', '\n'][..]).to_owned()
+                    }
+                    None => rpassword::read_password_from_tty(Some("Password: ")).context(
+                        "Error reading password (try passing the --password-stdin option)",
+                    )?,
                 };
                 let auth_key = api::authenticate(&username, &password).await?;
                 store_config(
-                    &config_path,
+                    config_path,
                     &Config {
                         auth_key: Some(auth_key),
                         ..config().clone()
@@ -467,7 +1442,7 @@ async fn try_main() -> anyhow::Result<()> {
             }
             Account::Logout {} => {
                 store_config(
-                    &config_path,
+                    config_path,
                     &Config {
                         auth_key: None,
                         ..config().clone()
@@ -476,30 +1451,36 @@ async fn try_main() -> anyhow::Result<()> {
                 .context("Error storing configuration with auth_key")?;
                 println!("Logged out!")
             }
-            Account::Create { file, name, lang } => {
-                let code = fs::read_to_string(&file)
-                    .with_context(|| format!("Couldn't read {}", file.display()))?;
+            Account::Create {
+                file,
+                name,
+                lang,
+                bundle,
+            } => {
+                let dir = robot_dir(&file)?;
                 let name = match name {
-                    Some(ref n) => n,
-                    None => robot_name_from_path(&file)?,
+                    Some(n) => n,
+                    None => upload_name(&file, dir.as_ref())?,
                 };
-                let lang = match lang {
+                let lang = match lang.or_else(|| dir.as_ref().and_then(|dir| dir.lang)) {
                     Some(l) => l,
                     None => file.extension().and_then(Lang::from_ext).ok_or_else(|| {
                         anyhow!("Invalid language from extension, try passing the -l option")
                     })?,
                 };
+                let code = upload_code(&file, lang, bundle)?;
+                let name = &name;
                 let info = api::create(lang, name).await?;
                 api::update_code(info.id, &code).await?;
                 println!("Robot {} created!", name)
             }
-            Account::Update { file, name } => {
-                let code = fs::read_to_string(&file)
-                    .with_context(|| format!("Couldn't read {}", file.display()))?;
+            Account::Update { file, name, bundle } => {
+                let dir = robot_dir(&file)?;
                 let name = match name {
-                    Some(ref n) => n,
-                    None => robot_name_from_path(&file)?,
+                    Some(n) => n,
+                    None => upload_name(&file, dir.as_ref())?,
                 };
+                let name = &name;
                 let (user, _) = api::whoami().await?;
                 let info = api::robot_info(&user, name).await?.ok_or_else(|| {
                     anyhow!(
@@ -507,9 +1488,71 @@ async fn try_main() -> anyhow::Result<()> {
                         name
                     )
                 })?;
+                let code = upload_code(&file, info.lang, bundle)?;
                 api::update_code(info.id, &code).await?;
                 println!("Robot {} updated!", name)
             }
+            Account::Diff { file, name, bundle } => {
+                let dir = robot_dir(&file)?;
+                let name = match name {
+                    Some(n) => n,
+                    None => upload_name(&file, dir.as_ref())?,
+                };
+                let (user, _) = api::whoami().await?;
+                let info = api::robot_info(&user, &name)
+                    .await?
+                    .ok_or_else(|| anyhow!("No existing robot of yours with name '{}'", name))?;
+                let published = api::robot_code(info.id).await?.unwrap_or_default();
+                let local = upload_code(&file, info.lang, bundle)?;
+                if published == local {
+                    println!("Robot {} is up to date", name);
+                } else {
+                    display::display_diff(
+                        &format!("{}/{} (published)", user, name),
+                        &file.display().to_string(),
+                        &published,
+                        &local,
+                    )?;
+                    return Err(ExitStatus {
+                        code: exit_code::DIFFERENT,
+                        msg: format!("Robot {} has unpublished changes", name),
+                    }
+                    .into());
+                }
+            }
+            Account::Sync { dir, force, bundle } => {
+                sync::sync(&dir, sync::SyncOptions { force, bundle }).await?;
+            }
+            Account::List { user } => {
+                let user = match user {
+                    Some(u) => u,
+                    None => api::whoami().await?.0,
+                };
+                let robots = api::robots(&user)
+                    .await?
+                    .ok_or_else(|| anyhow!("user {} not found", user))?;
+                if robots.is_empty() {
+                    println!("{} has no robots", user);
+                } else {
+                    display::display_robots(&robots)?;
+                }
+            }
+            Account::Info { slug } => {
+                let (user, robot) = parse_published_slug(&slug)
+                    .ok_or_else(|| anyhow!("invalid robot slug '{}'", slug))?;
+                let whoami;
+                let user = match user {
+                    Some(u) => u,
+                    None => {
+                        whoami = api::whoami().await?.0;
+                        &whoami
+                    }
+                };
+                let details = api::robot_details(user, robot)
+                    .await?
+                    .ok_or_else(|| anyhow!("robot {} not found", robot))?;
+                display::display_robot_details(user, &details)?;
+            }
             Account::Download { slug, dest } => {
                 let (user, robot) = parse_published_slug(&slug)
                     .ok_or_else(|| anyhow!("invalid robot slug '{}'", slug))?;
@@ -541,6 +1584,123 @@ async fn try_main() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Exit statuses for rumblebot, so that scripts (e.g. CI jobs) can tell why a match "failed"
+mod exit_code {
+    /// Rumblebot itself couldn't do what it was asked to, e.g. a robot couldn't be found or
+    /// the network was down
+    pub const FAILURE: i32 = 1;
+    /// The match didn't end with the expected result
+    pub const UNEXPECTED_RESULT: i32 = 2;
+    /// One of the robots errored during the match
+    pub const ROBOT_ERROR: i32 = 3;
+    /// `account diff` found differences between the local and published code
+    pub const DIFFERENT: i32 = 4;
+}
+
+/// An error that should make rumblebot exit with a specific status code instead of the generic
+/// `exit_code::FAILURE`
+#[derive(Debug)]
+struct ExitStatus {
+    code: i32,
+    msg: String,
+}
+
+impl std::fmt::Display for ExitStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(&self.msg)
+    }
+}
+
+impl std::error::Error for ExitStatus {}
+
+#[derive(Clone, Copy, PartialEq, Eq, Deserialize, strum::EnumString)]
+#[serde(rename_all = "lowercase")]
+#[strum(serialize_all = "lowercase", ascii_case_insensitive)]
+enum Expectation {
+    Blue,
+    Red,
+    Tie,
+}
+
+impl Expectation {
+    fn matches(self, winner: Option<logic::Team>) -> bool {
+        match (self, winner) {
+            (Self::Blue, Some(logic::Team::Blue)) => true,
+            (Self::Red, Some(logic::Team::Red)) => true,
+            (Self::Tie, None) => true,
+            _ => false,
+        }
+    }
+}
+
+fn check_match(
+    output: &MainOutput,
+    expect_winner: Option<Expectation>,
+    fail_on_robot_error: bool,
+) -> Result<(), ExitStatus> {
+    if fail_on_robot_error && !output.errors.is_empty() {
+        let teams = output
+            .errors
+            .keys()
+            .map(|team| format!("{:?}", team))
+            .join(" and ");
+        return Err(ExitStatus {
+            code: exit_code::ROBOT_ERROR,
+            msg: format!("{} errored during the match", teams),
+        });
+    }
+    if let Some(expected) = expect_winner {
+        if !expected.matches(output.winner) {
+            let result = match output.winner {
+                Some(team) => format!("{:?} won", team),
+                None => "it was a tie".to_owned(),
+            };
+            let expected = match expected {
+                Expectation::Blue => "Blue to win",
+                Expectation::Red => "Red to win",
+                Expectation::Tie => "a tie",
+            };
+            return Err(ExitStatus {
+                code: exit_code::UNEXPECTED_RESULT,
+                msg: format!("expected {}, but {}", expected, result),
+            });
+        }
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, strum::EnumString)]
+#[strum(serialize_all = "lowercase")]
+enum OutputFormat {
+    Human,
+    Json,
+    Ndjson,
+}
+
+/// Write a single `{"type": ..., "data": ...}` line to stdout and flush it right away, so that
+/// whatever is reading the stream can follow the game as it's played.
+fn write_ndjson_line(typ: &str, data: &impl serde::Serialize) -> std::io::Result<()> {
+    use std::io::Write;
+    let stdout = std::io::stdout();
+    let mut stdout = stdout.lock();
+    serde_json::to_writer(
+        &mut stdout,
+        &serde_json::json!({
+            "type": typ,
+            "data": data,
+        }),
+    )?;
+    writeln!(stdout)?;
+    stdout.flush()
+}
+
+fn parse_env_var(s: &str) -> anyhow::Result<(String, String)> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_owned(), value.to_owned())),
+        _ => bail!("expected KEY=VALUE, got {:?}", s),
+    }
+}
+
 fn parse_game_mode(game_mode_string: Option<OsString>) -> logic::GameMode {
     match game_mode_string {
         Some(s) => {
@@ -569,6 +1729,32 @@ fn robot_name_from_path(path: &Path) -> anyhow::Result<&str> {
         })
 }
 
+fn robot_dir(path: &Path) -> anyhow::Result<Option<manifest::RobotDir>> {
+    if path.is_dir() {
+        manifest::RobotDir::load(path).map(Some)
+    } else {
+        Ok(None)
+    }
+}
+
+/// The name to upload a robot as when it isn't passed explicitly
+fn upload_name(path: &Path, dir: Option<&manifest::RobotDir>) -> anyhow::Result<String> {
+    match dir.and_then(|dir| dir.manifest.name.clone()) {
+        Some(name) => Ok(name),
+        None => robot_name_from_path(path).map(str::to_owned),
+    }
+}
+
+/// The code to upload for a robot. robotrumble.org only takes a single file, so a robot directory
+/// (or a file, with `--bundle`) gets its local modules bundled into it
+fn upload_code(path: &Path, lang: Lang, bundle: bool) -> anyhow::Result<String> {
+    if bundle || path.is_dir() {
+        bundle::bundle(path, lang)
+    } else {
+        fs::read_to_string(path).with_context(|| format!("Couldn't read {}", path.display()))
+    }
+}
+
 fn directories() -> anyhow::Result<&'static directories::ProjectDirs> {
     static DIRS: OnceCell<directories::ProjectDirs> = OnceCell::new();
     DIRS.get_or_try_init(|| {
@@ -673,6 +1859,18 @@ pub enum RobotId {
         lang: Lang,
         source: String,
     },
+    Ghost {
+        transcript: PathBuf,
+    },
+    Builtin {
+        name: String,
+    },
+    Tcp {
+        address: String,
+    },
+    Unix {
+        path: PathBuf,
+    },
 }
 
 impl RobotId {
@@ -697,8 +1895,20 @@ impl RobotId {
                     .into(),
             ),
             Self::Inline { .. } => (".inline", ".".into()),
+            Self::Ghost { transcript } => (".ghost", transcript.to_string_lossy()),
+            Self::Builtin { name } => (".builtin", name.into()),
+            Self::Tcp { address } => (".tcp", address.into()),
+            Self::Unix { path } => (".unix", path.to_string_lossy()),
         }
     }
+    /// Whether the robot runs outside of the wasm sandbox, or is a connection to something that
+    /// does, so that it can do anything a normal program can
+    pub fn is_native(&self) -> bool {
+        matches!(
+            self,
+            Self::Command { .. } | Self::LocalRunner { .. } | Self::Tcp { .. } | Self::Unix { .. }
+        )
+    }
     pub fn parse(s: &OsStr) -> anyhow::Result<Self> {
         let s = match s.to_str() {
             Some(s) => s,
@@ -749,6 +1959,35 @@ impl RobotId {
                         source: source.to_owned(),
                     })
                 }
+                "ghost" => Ok(RobotId::Ghost {
+                    transcript: PathBuf::from(content),
+                }),
+                "builtin" => {
+                    if !builtin::names().any(|name| name == content) {
+                        bail!(
+                            "unknown builtin robot {:?}; the builtin robots are: {}",
+                            content,
+                            builtin::names().join(", ")
+                        )
+                    }
+                    Ok(RobotId::Builtin {
+                        name: content.to_owned(),
+                    })
+                }
+                "tcp" => {
+                    let valid = content
+                        .rsplit_once(':')
+                        .map_or(false, |(host, port)| !host.is_empty() && port.parse::<u16>().is_ok());
+                    if !valid {
+                        bail!("invalid tcp robot address {:?}; it must be in the form of `host:port`", content)
+                    }
+                    Ok(RobotId::Tcp {
+                        address: content.to_owned(),
+                    })
+                }
+                "unix" => Ok(RobotId::Unix {
+                    path: PathBuf::from(content),
+                }),
                 _ => {
                     if typ.len() == 1 && typ.chars().next().unwrap().is_uppercase(){
                         Self::from_path(PathBuf::from(s))
@@ -763,6 +2002,40 @@ impl RobotId {
             Self::from_path(PathBuf::from(s))
         }
     }
+    pub fn display_name(&self) -> String {
+        let (user, robot) = self.display_id();
+        format!("{}/{}", user, robot)
+    }
+    /// The path to the robot's code, if it's available locally
+    fn source_name(&self) -> Option<String> {
+        let file = match self {
+            Self::Local { source, .. } => manifest::entry_file(source),
+            Self::LocalRunner { source, .. } => manifest::entry_file(Path::new(source)),
+            _ => return None,
+        };
+        file.ok().map(|file| file.display().to_string())
+    }
+    /// The robot's code, if it's available locally
+    fn source_file(&self) -> Option<display::SourceFile> {
+        let (name, code) = match self {
+            Self::Local { source, .. } => {
+                let file = manifest::entry_file(source).ok()?;
+                (file.display().to_string(), fs::read_to_string(&file).ok()?)
+            }
+            Self::LocalRunner { source, .. } => {
+                let file = manifest::entry_file(Path::new(source)).ok()?;
+                (file.display().to_string(), fs::read_to_string(&file).ok()?)
+            }
+            Self::Inline { source, .. } => ("<inline>".to_owned(), source.clone()),
+            Self::Published { .. }
+            | Self::Command { .. }
+            | Self::Ghost { .. }
+            | Self::Builtin { .. }
+            | Self::Tcp { .. }
+            | Self::Unix { .. } => return None,
+        };
+        Some(display::SourceFile { name, code })
+    }
     fn valid_ident(s: &str) -> bool {
         !s.is_empty()
             && s.chars()
@@ -777,6 +2050,17 @@ impl RobotId {
         })
     }
     fn from_path(source: PathBuf) -> anyhow::Result<Self> {
+        if source.is_dir() {
+            let robot = manifest::RobotDir::load(&source)?;
+            let lang = robot.lang.ok_or_else(|| {
+                anyhow!(
+                    "couldn't tell what language {} is in; add a `lang` to its {}",
+                    robot.entry.display(),
+                    manifest::MANIFEST_FILE
+                )
+            })?;
+            return Ok(RobotId::Local { source, lang });
+        }
         let ext = source.extension().ok_or_else(|| {
             anyhow!("your robot file must have an extension so that we know what language it's in")
         })?;
@@ -807,23 +2091,125 @@ fn parse_published_slug(s: &str) -> Option<(Option<&str>, &str)> {
     Some(ret)
 }
 
+/// Start up a robot and shut it down again, to see whether it initializes
+async fn check_robot(
+    id: &RobotId,
+    limits: &command::CommandLimits,
+) -> anyhow::Result<logic::ProgramResult<()>> {
+    let stderr = logs::StderrCapture::new(id.display_name());
+    let opts = RunnerOptions {
+        stderr: Some(stderr.clone()),
+        limits: limits.clone(),
+        ..Default::default()
+    };
+    match Runner::from_id(id, &opts).await? {
+        Ok(_) => Ok(Ok(())),
+        // only wait for the rest of stderr when there's an error to attach it to
+        Err(mut err) => {
+            stderr.drain().await;
+            logs::attach_stderr(&mut err, &stderr.tail());
+            Ok(Err(err))
+        }
+    }
+}
+
+#[derive(Clone, Copy, strum::EnumString)]
+#[strum(serialize_all = "lowercase")]
+enum CheckFormat {
+    Human,
+    Json,
+}
+
+/// A robot's error in a form that's easy for other tools to consume
+fn diagnostic(file: Option<&str>, err: &logic::ProgramError) -> serde_json::Value {
+    match err {
+        logic::ProgramError::InitError(error) => {
+            let loc = error.loc.as_ref();
+            serde_json::json!({
+                "file": file,
+                "line": loc.map(|loc| loc.start.0),
+                "column": loc.and_then(|loc| loc.start.1),
+                "message": error.summary,
+                "details": error.details,
+            })
+        }
+        err => serde_json::json!({
+            "file": file,
+            "line": null,
+            "column": null,
+            "message": format!("{:?}", err),
+            "details": null,
+        }),
+    }
+}
+
+/// Settings for a single game that aren't part of its `GameSpec`
+#[derive(Default)]
+struct RunOptions {
+    log_dir: Option<PathBuf>,
+    record_io: Option<PathBuf>,
+    limits: command::CommandLimits,
+    /// Filled in with the turn each robot errored on, for the teams that are in it
+    error_turns: BTreeMap<logic::Team, ErrorTurn>,
+}
+
 async fn run_game(
-    spec: GameSpec,
+    mut spec: GameSpec,
     game_mode: GameMode,
-    display_turns: bool,
-    red_logs_only: bool,
-    blue_logs_only: bool,
+    opts: &RunOptions,
+    mut on_turn: impl FnMut(&logic::CallbackInput),
 ) -> anyhow::Result<MainOutput> {
     let setup_time_start = Instant::now();
 
-    let get_runner = |id| async move {
-        let id = RobotId::parse(id).context("Couldn't parse robot identifier")?;
-        let runner = Runner::from_id(&id).await?;
-        Ok::<_, anyhow::Error>(runner)
+    let stderr = maplit::btreemap! {
+        logic::Team::Blue => logs::StderrCapture::for_team(logic::Team::Blue),
+        logic::Team::Red => logs::StderrCapture::for_team(logic::Team::Red),
+    };
+    let mut log_files = opts
+        .log_dir
+        .as_deref()
+        .map(logs::LogFiles::create)
+        .transpose()?;
+    if log_files.is_some() {
+        stderr.values().for_each(logs::StderrCapture::keep_for_log);
+    }
+
+    // pick the seed here rather than leaving it to the game, so that the builtin robots' choices
+    // follow from it too, and the whole game can be replayed with it
+    let seed = match &spec.seed {
+        Some(seed) => seed.clone(),
+        None => {
+            let seed = telemetry::random_seed();
+            eprintln!("Playing with seed {}", seed);
+            spec.seed = Some(seed.clone());
+            seed
+        }
+    };
+    let get_runner = |id, team, env| {
+        let runner_opts = RunnerOptions {
+            stderr: Some(stderr[&team].clone()),
+            env,
+            record_io: opts
+                .record_io
+                .as_ref()
+                .map(|dir| dir.join(format!("{}.ndjson", format!("{:?}", team).to_lowercase()))),
+            limits: opts.limits.clone(),
+            // so that two builtin robots on different teams don't make the same choices
+            seed: Some(format!("{}/{:?}", seed, team)),
+            error_turn: opts.error_turns.get(&team).cloned(),
+        };
+        async move {
+            let id = RobotId::parse(id).context("Couldn't parse robot identifier")?;
+            let runner = Runner::from_id(&id, &runner_opts).await?;
+            Ok::<_, anyhow::Error>(runner)
+        }
     };
     let blue_os: OsString = spec.blue.into();
     let red_os: OsString = spec.red.into();
-    let (blue, red) = tokio::try_join!(get_runner(&blue_os), get_runner(&red_os))?;
+    let (blue, red) = tokio::try_join!(
+        get_runner(&blue_os, logic::Team::Blue, spec.blue_env),
+        get_runner(&red_os, logic::Team::Red, spec.red_env)
+    )?;
     let runners = maplit::btreemap! {
         logic::Team::Blue => blue,
         logic::Team::Red => red,
@@ -832,18 +2218,20 @@ async fn run_game(
     let setup_time_end = Instant::now();
     eprintln!("Setup took {:?}", setup_time_end - setup_time_start);
 
-    let output = logic::run(
+    let mut output = logic::run(
         runners,
         |turn_state| {
-            if display_turns {
-                display::display_turn(turn_state, !red_logs_only, !blue_logs_only)
-                    .expect("printing failed");
+            if let Some(log_files) = &mut log_files {
+                log_files
+                    .write_turn(turn_state, &stderr)
+                    .expect("writing logs failed");
             }
+            on_turn(turn_state)
         },
         spec.turn_num.unwrap_or(100),
         true,
         None,
-        game_mode,
+        spec.game_mode.unwrap_or(game_mode),
         spec.seed.map(|s| s).as_deref(),
     )
     .await;
@@ -851,6 +2239,17 @@ async fn run_game(
     let game_end_time = Instant::now();
     eprintln!("Game took {:?}", game_end_time - setup_time_end);
 
+    // the robots were stopped when the game ended, so their stderr will be closed shortly
+    for capture in stderr.values() {
+        capture.drain().await;
+    }
+    for (team, err) in &mut output.errors {
+        logs::attach_stderr(err, &stderr[team].tail());
+    }
+    if let Some(log_files) = log_files {
+        log_files.finish(&stderr)?;
+    }
+
     Ok(output)
 }
 
@@ -859,5 +2258,32 @@ struct GameSpec {
     red: String,
     blue: String,
     seed: Option<String>,
-    turn_num: Option<usize>
+    turn_num: Option<usize>,
+    #[serde(default)]
+    expect_winner: Option<Expectation>,
+    /// Overrides the game mode passed to `run_game`
+    #[serde(default)]
+    game_mode: Option<GameMode>,
+    /// Environment variables for each robot, like `--blue-env` and `--red-env`
+    #[serde(default)]
+    blue_env: BTreeMap<String, String>,
+    #[serde(default)]
+    red_env: BTreeMap<String, String>,
+}
+
+impl GameSpec {
+    fn display_name(&self) -> String {
+        format!("{} vs {}", self.blue, self.red)
+    }
+    fn to_json(&self) -> serde_json::Value {
+        serde_json::json!({
+            "blue": self.blue,
+            "red": self.red,
+            "seed": self.seed,
+            "turn_num": self.turn_num,
+            "game_mode": self.game_mode.map(|mode| format!("{:?}", mode)),
+            "blue_env": self.blue_env,
+            "red_env": self.red_env,
+        })
+    }
 }
@@ -0,0 +1,117 @@
+use anyhow::{anyhow, bail, Context};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use super::Lang;
+
+/// The name of the manifest file in a robot directory
+pub const MANIFEST_FILE: &str = "robot.toml";
+
+/// The files that are tried, in order, when a robot directory's manifest doesn't have an `entry`
+const DEFAULT_ENTRIES: &[&str] = &["main.py", "main.js", "index.js", "robot.py", "robot.js"];
+
+/// `robot.toml`, the optional manifest for a robot that's a directory rather than a single file
+#[derive(Deserialize, Default)]
+pub struct Manifest {
+    /// The name of the robot on robotrumble.org. Defaults to the name of the directory
+    pub name: Option<String>,
+    /// Defaults to the language of the entry file's extension
+    pub lang: Option<Lang>,
+    /// The file to run, relative to the robot directory
+    pub entry: Option<PathBuf>,
+    /// Paths to `.whl` or `.tgz` packages to make available to the robot, relative to the robot
+    /// directory. Anything in its `vendor/` directory is made available as well
+    #[serde(default)]
+    pub vendor: Vec<PathBuf>,
+    /// Environment variables to set for the robot, e.g. to tune constants without editing the code
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+}
+
+impl Manifest {
+    /// Load the manifest from a robot directory, or the default one if there isn't any
+    pub fn load(dir: &Path) -> anyhow::Result<Self> {
+        let path = dir.join(MANIFEST_FILE);
+        match fs::read_to_string(&path) {
+            Ok(s) => toml::from_str(&s).with_context(|| format!("Invalid {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => {
+                Err(anyhow::Error::new(e).context(format!("Couldn't read {}", path.display())))
+            }
+        }
+    }
+}
+
+/// A robot whose code is spread across a directory
+pub struct RobotDir {
+    pub manifest: Manifest,
+    /// The file to run, relative to the robot directory
+    pub entry: PathBuf,
+    pub lang: Option<Lang>,
+}
+
+impl RobotDir {
+    pub fn load(dir: &Path) -> anyhow::Result<Self> {
+        let manifest = Manifest::load(dir)?;
+        let entry = match &manifest.entry {
+            Some(entry) => {
+                let inside_dir = entry
+                    .components()
+                    .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+                if !inside_dir {
+                    bail!(
+                        "the entry {} in {} must be a relative path inside the robot directory",
+                        entry.display(),
+                        MANIFEST_FILE
+                    );
+                }
+                if !dir.join(entry).is_file() {
+                    bail!("the entry file {} doesn't exist", dir.join(entry).display());
+                }
+                entry.clone()
+            }
+            None => DEFAULT_ENTRIES
+                .iter()
+                .map(PathBuf::from)
+                .find(|entry| dir.join(entry).is_file())
+                .ok_or_else(|| {
+                    anyhow!(
+                        "couldn't find the entry file for the robot directory {}; add an `entry` to its {}",
+                        dir.display(),
+                        MANIFEST_FILE
+                    )
+                })?,
+        };
+        let lang = manifest
+            .lang
+            .or_else(|| entry.extension().and_then(Lang::from_ext));
+        Ok(Self {
+            manifest,
+            entry,
+            lang,
+        })
+    }
+
+    /// The entry's path as the robot sees it, relative to `/source`
+    pub fn wasi_entry(&self) -> String {
+        self.entry
+            .components()
+            .filter_map(|c| match c {
+                Component::Normal(s) => Some(s.to_string_lossy()),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+}
+
+/// The file with a robot's code in it, which is the entry file for a robot directory
+pub fn entry_file(source: &Path) -> anyhow::Result<PathBuf> {
+    if source.is_dir() {
+        Ok(source.join(RobotDir::load(source)?.entry))
+    } else {
+        Ok(source.to_owned())
+    }
+}
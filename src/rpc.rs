@@ -0,0 +1,288 @@
+//! A JSON-RPC 2.0 server over stdin/stdout, for editor integrations. Each message is a single
+//! line of JSON.
+
+use logic::RobotRunner;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::sync::{Arc, Mutex};
+use tokio::io::{self, AsyncBufReadExt, AsyncWriteExt};
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+
+use super::command::CommandLimits;
+use super::{run_game, GameSpec, RobotId, RunOptions, Runner, RunnerOptions};
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+/// The request couldn't be carried out, e.g. a robot couldn't be found
+const SERVER_ERROR: i64 = -32000;
+/// Same as in the language server protocol
+const REQUEST_CANCELLED: i64 = -32800;
+
+#[derive(Deserialize)]
+struct Request {
+    /// Notifications don't have an id, and don't get a response
+    #[serde(default)]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+struct RpcError {
+    code: i64,
+    message: String,
+    data: Option<Value>,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+            data: None,
+        }
+    }
+}
+
+impl From<anyhow::Error> for RpcError {
+    fn from(err: anyhow::Error) -> Self {
+        Self::new(SERVER_ERROR, format!("{:#}", err))
+    }
+}
+
+type RpcResult = Result<Value, RpcError>;
+
+#[derive(Clone)]
+struct Client {
+    tx: mpsc::UnboundedSender<Value>,
+}
+
+impl Client {
+    fn respond(&self, id: Value, result: RpcResult) {
+        let msg = match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(err) => {
+                let mut error = json!({ "code": err.code, "message": err.message });
+                if let Some(data) = err.data {
+                    error["data"] = data;
+                }
+                json!({ "jsonrpc": "2.0", "id": id, "error": error })
+            }
+        };
+        let _ = self.tx.send(msg);
+    }
+
+    fn notify(&self, method: &str, params: Value) {
+        let _ = self
+            .tx
+            .send(json!({ "jsonrpc": "2.0", "method": method, "params": params }));
+    }
+}
+
+/// Requests that are still running, keyed by their serialized id, so that they can be cancelled
+type Running = Arc<Mutex<HashMap<String, JoinHandle<()>>>>;
+
+pub async fn serve(limits: CommandLimits) -> anyhow::Result<()> {
+    let limits = Arc::new(limits);
+    let (tx, mut rx) = mpsc::unbounded_channel::<Value>();
+    let client = Client { tx };
+
+    let writer = tokio::spawn(async move {
+        let mut stdout = io::stdout();
+        while let Some(msg) = rx.recv().await {
+            let mut line = serde_json::to_vec(&msg).unwrap();
+            line.push(b'\n');
+            stdout.write_all(&line).await?;
+            stdout.flush().await?;
+        }
+        Ok::<_, io::Error>(())
+    });
+
+    let running = Running::default();
+    let mut stdin = io::BufReader::new(io::stdin()).lines();
+    while let Some(line) = stdin.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let request = match serde_json::from_str::<Value>(&line) {
+            Ok(value) => serde_json::from_value::<Request>(value),
+            Err(err) => {
+                client.respond(
+                    Value::Null,
+                    Err(RpcError::new(PARSE_ERROR, err.to_string())),
+                );
+                continue;
+            }
+        };
+        match request {
+            Ok(request) => handle(request, &client, &running, &limits),
+            Err(err) => {
+                client.respond(
+                    Value::Null,
+                    Err(RpcError::new(INVALID_REQUEST, err.to_string())),
+                );
+            }
+        }
+    }
+
+    // stdin is closed, so nobody is going to read any more responses
+    for (_, task) in running.lock().unwrap().drain() {
+        task.abort();
+    }
+    drop(client);
+    writer.await??;
+    Ok(())
+}
+
+fn handle(request: Request, client: &Client, running: &Running, limits: &Arc<CommandLimits>) {
+    let Request { id, method, params } = request;
+
+    if method == "cancel" {
+        let result = cancel(params, client, running);
+        if let Some(id) = id {
+            client.respond(id, result);
+        }
+        return;
+    }
+
+    let key = id.as_ref().map(Value::to_string);
+    // hold the lock while spawning, so that the task can't finish and try to remove itself
+    // before it's been added
+    let mut tasks = running.lock().unwrap();
+    if let (Some(id), Some(key)) = (&id, &key) {
+        // the first request would be left running with no way to cancel it
+        if tasks.contains_key(key) {
+            client.respond(
+                id.clone(),
+                Err(RpcError::new(
+                    INVALID_REQUEST,
+                    format!("request {} is still running", key),
+                )),
+            );
+            return;
+        }
+    }
+    let task = {
+        let client = client.clone();
+        let running = running.clone();
+        let key = key.clone();
+        let limits = limits.clone();
+        tokio::spawn(async move {
+            let result = match method.as_str() {
+                "runGame" => run_game_method(params, id.clone(), &client, &limits).await,
+                "runTurn" => run_turn(params, &limits).await,
+                "checkRobot" => check_robot(params, &limits).await,
+                _ => Err(RpcError::new(
+                    METHOD_NOT_FOUND,
+                    format!("unknown method {:?}", method),
+                )),
+            };
+            if let Some(key) = key {
+                running.lock().unwrap().remove(&key);
+            }
+            if let Some(id) = id {
+                client.respond(id, result);
+            }
+        })
+    };
+    if let Some(key) = key {
+        tasks.insert(key, task);
+    }
+}
+
+fn parse_params<T: serde::de::DeserializeOwned>(params: Value) -> Result<T, RpcError> {
+    serde_json::from_value(params).map_err(|err| RpcError::new(INVALID_PARAMS, err.to_string()))
+}
+
+fn parse_robot(robot: &str) -> Result<RobotId, RpcError> {
+    RobotId::parse(OsStr::new(robot))
+        .map_err(|err| RpcError::new(INVALID_PARAMS, format!("{:#}", err)))
+}
+
+/// `{"id": ...}`: stop a running request. It responds with a `REQUEST_CANCELLED` error
+fn cancel(params: Value, client: &Client, running: &Running) -> RpcResult {
+    #[derive(Deserialize)]
+    struct Params {
+        id: Value,
+    }
+    let Params { id } = parse_params(params)?;
+    let task = running.lock().unwrap().remove(&id.to_string());
+    match task {
+        Some(task) => {
+            task.abort();
+            client.respond(
+                id,
+                Err(RpcError::new(REQUEST_CANCELLED, "request cancelled")),
+            );
+            Ok(json!(true))
+        }
+        None => Ok(json!(false)),
+    }
+}
+
+/// A `GameSpec`, like for `run batch`. Sends a `progress` notification with
+/// `{"id": $REQUEST_ID, "turn": $CALLBACK_INPUT}` after every turn, and responds with the
+/// `MainOutput`
+async fn run_game_method(
+    params: Value,
+    id: Option<Value>,
+    client: &Client,
+    limits: &CommandLimits,
+) -> RpcResult {
+    let spec: GameSpec = parse_params(params)?;
+    let opts = RunOptions {
+        limits: limits.clone(),
+        ..Default::default()
+    };
+    let output = run_game(spec, logic::GameMode::Normal, &opts, |turn| {
+        client.notify("progress", json!({ "id": id, "turn": turn }))
+    })
+    .await?;
+    Ok(json!(output))
+}
+
+/// `{"robot": ..., "input": $PROGRAM_INPUT}`: run a single turn of a robot, responding with its
+/// `ProgramOutput`. If the robot errors, the `ProgramError` is in the error's `data`
+async fn run_turn(params: Value, limits: &CommandLimits) -> RpcResult {
+    #[derive(Deserialize)]
+    struct Params {
+        robot: String,
+        input: logic::ProgramInput<'static>,
+    }
+    let Params { robot, input } = parse_params(params)?;
+    let id = parse_robot(&robot)?;
+    let opts = RunnerOptions {
+        limits: limits.clone(),
+        ..Default::default()
+    };
+    let result = match Runner::from_id(&id, &opts).await? {
+        Ok(mut runner) => runner.run(input).await,
+        Err(err) => Err(err),
+    };
+    result.map(|output| json!(output)).map_err(|err| RpcError {
+        code: SERVER_ERROR,
+        message: "the robot errored".to_owned(),
+        data: Some(json!(err)),
+    })
+}
+
+/// `{"robot": ...}`: make sure a robot compiles and initializes, responding with
+/// `{"ok": true}` or `{"ok": false, "error": $PROGRAM_ERROR}`
+async fn check_robot(params: Value, limits: &CommandLimits) -> RpcResult {
+    #[derive(Deserialize)]
+    struct Params {
+        robot: String,
+    }
+    let Params { robot } = parse_params(params)?;
+    let id = parse_robot(&robot)?;
+    let result = match super::check_robot(&id, limits).await? {
+        Ok(()) => json!({ "ok": true }),
+        Err(err) => json!({ "ok": false, "error": err }),
+    };
+    Ok(result)
+}
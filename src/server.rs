@@ -9,19 +9,30 @@ use tokio::{net, sync::mpsc, task};
 use warp::sse::Event;
 use warp::Filter;
 
-use super::{RobotId, Runner};
+use super::command::CommandLimits;
+use super::{RobotId, Runner, RunnerOptions};
 
 #[derive(Clone)]
 struct Context {
     r1: OwningRef<Arc<Vec<RobotId>>, RobotId>,
     ids: Arc<Vec<RobotId>>,
+    limits: Arc<CommandLimits>,
 }
 
-pub async fn serve(ids: Vec<RobotId>, address: String, port: Option<u16>) -> anyhow::Result<()> {
+pub async fn serve(
+    ids: Vec<RobotId>,
+    address: String,
+    port: Option<u16>,
+    limits: CommandLimits,
+) -> anyhow::Result<()> {
     let ids = Arc::new(ids);
     let r1 = OwningRef::new(ids.clone()).map(|v| v.first().unwrap());
 
-    let ctx = Context { r1, ids };
+    let ctx = Context {
+        r1,
+        ids,
+        limits: Arc::new(limits),
+    };
     let ctx = warp::any().map(move || ctx.clone());
 
     let route = warp::path("getflags")
@@ -106,14 +117,18 @@ struct RunParams {
 }
 
 async fn run(
-    Context { r1, ids }: Context,
+    Context { r1, ids, limits }: Context,
     params: RunParams,
 ) -> Result<impl warp::Reply, warp::Rejection> {
     let r2 = OwningRef::new(ids).try_map(|ids| ids.get(params.id).ok_or_else(|| warp::reject()))?;
     let (tx, rx) = mpsc::unbounded_channel();
     task::spawn(async move {
+        let opts = RunnerOptions {
+            limits: (*limits).clone(),
+            ..Default::default()
+        };
         let make_runner = |id| {
-            Runner::from_id(id)
+            Runner::from_id(id, &opts)
                 .map(|res| res.unwrap_or_else(|err| Err(logic::ProgramError::IO(err.to_string()))))
         };
         let (r1, r2) = tokio::join!(make_runner(&r1), make_runner(&r2));
@@ -0,0 +1,74 @@
+//! Robots that are already running somewhere else, and that we connect to over a socket with
+//! `tcp:` or `unix:`. They speak the same protocol as `command:` robots, just over the socket
+//! instead of stdin/stdout.
+
+use logic::{ProgramError, ProgramResult};
+use native_runner::TokioRunner;
+use std::fmt::Display;
+use std::io;
+use std::path::Path;
+use tokio::io::{AsyncBufRead, AsyncRead, AsyncWrite, BufReader, BufWriter};
+use tokio::time;
+
+/// How long to wait for the robot to accept the connection
+const CONNECT_TIMEOUT: time::Duration = time::Duration::from_secs(10);
+
+pub type SocketRunner = TokioRunner<
+    Box<dyn AsyncWrite + Send + Sync + Unpin>,
+    Box<dyn AsyncBufRead + Send + Sync + Unpin>,
+>;
+
+/// Connect to a robot listening on `host:port`
+pub async fn connect_tcp(address: &str) -> ProgramResult<SocketRunner> {
+    let stream = connect(address, tokio::net::TcpStream::connect(address)).await?;
+    // the protocol is a line at a time, so don't hold any of them back
+    let _ = stream.set_nodelay(true);
+    let (read, write) = stream.into_split();
+    start(read, write).await
+}
+
+/// Connect to a robot listening on a unix socket
+#[cfg(unix)]
+pub async fn connect_unix(path: &Path) -> ProgramResult<SocketRunner> {
+    let stream = connect(path.display(), tokio::net::UnixStream::connect(path)).await?;
+    let (read, write) = stream.into_split();
+    start(read, write).await
+}
+
+#[cfg(not(unix))]
+pub async fn connect_unix(path: &Path) -> ProgramResult<SocketRunner> {
+    Err(ProgramError::IO(format!(
+        "can't connect to {}: unix sockets aren't supported on this platform",
+        path.display()
+    )))
+}
+
+async fn connect<S>(
+    address: impl Display,
+    connecting: impl std::future::Future<Output = io::Result<S>>,
+) -> ProgramResult<S> {
+    match time::timeout(CONNECT_TIMEOUT, connecting).await {
+        Ok(Ok(stream)) => Ok(stream),
+        Ok(Err(err)) => Err(ProgramError::IO(format!(
+            "couldn't connect to {}: {}",
+            address, err
+        ))),
+        Err(_) => Err(ProgramError::IO(format!(
+            "couldn't connect to {}: timed out after {:?}",
+            address, CONNECT_TIMEOUT
+        ))),
+    }
+}
+
+/// Wait for the robot to say that it's initialized. If the connection is closed at any point,
+/// that's an IO error from the runner, just like a `command:` robot exiting
+async fn start(
+    read: impl AsyncRead + Send + Sync + Unpin + 'static,
+    write: impl AsyncWrite + Send + Sync + Unpin + 'static,
+) -> ProgramResult<SocketRunner> {
+    TokioRunner::new(
+        Box::new(BufWriter::new(write)) as Box<dyn AsyncWrite + Send + Sync + Unpin>,
+        Box::new(BufReader::new(read)) as Box<dyn AsyncBufRead + Send + Sync + Unpin>,
+    )
+    .await
+}
@@ -0,0 +1,304 @@
+//! `account sync`, which keeps a directory of robots and the robots on robotrumble.org in step.
+//!
+//! Every robot file or robot directory in the synced directory is matched up with the robot of the
+//! same name (or the name in its manifest). The hash of each robot's code as of the last sync is
+//! kept in the directory, so that we can tell which side changed since then and never overwrite a
+//! change without being asked to.
+
+use anyhow::{anyhow, bail, Context};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::{api, Lang};
+
+/// The name of the file that the sync state is kept in, inside the synced directory
+const STATE_FILE: &str = ".rumblebot-sync.toml";
+
+#[derive(Serialize, Deserialize, Default)]
+struct State {
+    /// The hash of each robot's code as of the last sync, by name
+    #[serde(default)]
+    robots: BTreeMap<String, String>,
+}
+
+impl State {
+    fn load(dir: &Path) -> anyhow::Result<Self> {
+        let path = dir.join(STATE_FILE);
+        match fs::read_to_string(&path) {
+            Ok(s) => toml::from_str(&s).with_context(|| format!("Invalid {}", path.display())),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => {
+                Err(anyhow::Error::new(e).context(format!("Couldn't read {}", path.display())))
+            }
+        }
+    }
+
+    /// Stored after every robot, so that an error partway through doesn't lose what was synced
+    fn store(&self, dir: &Path) -> anyhow::Result<()> {
+        let path = dir.join(STATE_FILE);
+        fs::write(&path, toml::to_string_pretty(self)?)
+            .with_context(|| format!("Couldn't write {}", path.display()))
+    }
+}
+
+fn hash(code: &str) -> String {
+    format!("{:x}", Sha256::digest(code.as_bytes()))
+}
+
+/// A robot file or robot directory in the synced directory
+struct LocalRobot {
+    path: PathBuf,
+    lang: Lang,
+    code: String,
+    /// Whether `code` is the file's contents as-is, so that it can be overwritten with the code
+    /// from the site. Bundled code can't be turned back into the files it came from
+    pullable: bool,
+}
+
+fn local_robots(dir: &Path, bundle: bool) -> anyhow::Result<BTreeMap<String, LocalRobot>> {
+    let mut paths = fs::read_dir(dir)
+        .with_context(|| format!("Couldn't read directory {}", dir.display()))?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.sort();
+
+    let mut robots = BTreeMap::new();
+    for path in paths {
+        let hidden = path
+            .file_name()
+            .and_then(|s| s.to_str())
+            .map_or(true, |s| s.starts_with('.'));
+        if hidden {
+            continue;
+        }
+        let robot_dir = super::robot_dir(&path)?;
+        let lang = match &robot_dir {
+            Some(robot_dir) => robot_dir.lang.ok_or_else(|| {
+                anyhow!(
+                    "Couldn't tell the language of {}; add a `lang` to its manifest",
+                    path.display()
+                )
+            })?,
+            None => match path.extension().and_then(Lang::from_ext) {
+                Some(lang) => lang,
+                // not a robot
+                None => continue,
+            },
+        };
+        let name = super::upload_name(&path, robot_dir.as_ref())?;
+        let code = super::upload_code(&path, lang, bundle)?;
+        let robot = LocalRobot {
+            pullable: robot_dir.is_none() && !bundle,
+            path,
+            lang,
+            code,
+        };
+        if let Some(other) = robots.insert(name.clone(), robot) {
+            bail!(
+                "{} and {} are both the robot {}",
+                other.path.display(),
+                robots[&name].path.display(),
+                name
+            );
+        }
+    }
+    Ok(robots)
+}
+
+/// What to do with a robot that exists both locally and on the site
+#[derive(Debug, PartialEq)]
+enum Action {
+    UpToDate,
+    /// Overwrite the site's code with the local code
+    Push,
+    /// Overwrite the local file with the site's code
+    Pull,
+    /// Both sides changed since the last sync, or it's unknown which did
+    Conflict,
+}
+
+/// Decide what to do from the hashes of the local code, the site's code (`None` if the robot has
+/// no code there yet) and the code as of the last sync
+fn decide(
+    local: &str,
+    remote: Option<&str>,
+    last: Option<&str>,
+    pullable: bool,
+    force: bool,
+) -> Action {
+    let remote = match remote {
+        Some(remote) => remote,
+        // there's nothing to pull, and nothing on the site to lose
+        None => return Action::Push,
+    };
+    if local == remote {
+        Action::UpToDate
+    } else if last == Some(local) && pullable {
+        Action::Pull
+    } else if last == Some(remote) || force {
+        Action::Push
+    } else {
+        Action::Conflict
+    }
+}
+
+pub struct SyncOptions {
+    /// Overwrite robots on the site that were changed both locally and on the site
+    pub force: bool,
+    /// Bundle the local modules that robot files import, like `account update --bundle`
+    pub bundle: bool,
+}
+
+/// Sync `dir` with the logged in user's robots. Robots that can't be synced because they changed
+/// on both sides are skipped, and reported in the error at the end
+pub async fn sync(dir: &Path, opts: SyncOptions) -> anyhow::Result<()> {
+    let (user, _) = api::whoami().await?;
+    let mut state = State::load(dir)?;
+    let local = local_robots(dir, opts.bundle)?;
+    let remote = api::robots(&user)
+        .await?
+        .ok_or_else(|| anyhow!("user {} not found", user))?
+        .into_iter()
+        .map(|robot| (robot.name.clone(), robot))
+        .collect::<BTreeMap<_, _>>();
+
+    let names: BTreeSet<&String> = local.keys().chain(remote.keys()).collect();
+    let mut conflicts = Vec::new();
+    for name in names {
+        let synced = match (local.get(name), remote.get(name)) {
+            (Some(local), None) => {
+                let info = api::create(local.lang, name).await?;
+                api::update_code(info.id, &local.code).await?;
+                println!("Robot {} created from {}", name, local.path.display());
+                hash(&local.code)
+            }
+            (None, Some(remote)) => {
+                let code = match api::working_code(remote.id).await? {
+                    Some(code) => code,
+                    None => {
+                        println!("Robot {} has no code yet, skipping it", name);
+                        continue;
+                    }
+                };
+                let path = dir.join(format!("{}.{}", name, remote.lang.ext()));
+                fs::write(&path, &code)
+                    .with_context(|| format!("Couldn't write {}", path.display()))?;
+                println!("Robot {} downloaded to {}", name, path.display());
+                hash(&code)
+            }
+            (Some(local), Some(remote)) => {
+                let remote_code = api::working_code(remote.id).await?;
+                let local_hash = hash(&local.code);
+                let remote_hash = remote_code.as_deref().map(hash);
+                let last = state.robots.get(name.as_str()).map(String::as_str);
+                let action = decide(
+                    &local_hash,
+                    remote_hash.as_deref(),
+                    last,
+                    local.pullable,
+                    opts.force,
+                );
+                match (action, remote_code, remote_hash) {
+                    (Action::UpToDate, ..) => local_hash,
+                    (Action::Pull, Some(remote_code), Some(remote_hash)) => {
+                        fs::write(&local.path, &remote_code)
+                            .with_context(|| format!("Couldn't write {}", local.path.display()))?;
+                        println!("Robot {} downloaded to {}", name, local.path.display());
+                        remote_hash
+                    }
+                    (Action::Push, ..) => {
+                        api::update_code(remote.id, &local.code).await?;
+                        println!("Robot {} updated from {}", name, local.path.display());
+                        local_hash
+                    }
+                    (Action::Conflict, ..) => {
+                        conflicts.push(name.as_str());
+                        continue;
+                    }
+                    (Action::Pull, ..) => {
+                        unreachable!("only robots with code on the site are pulled")
+                    }
+                }
+            }
+            (None, None) => unreachable!(),
+        };
+        if state.robots.get(name.as_str()) != Some(&synced) {
+            state.robots.insert(name.clone(), synced);
+            state.store(dir)?;
+        }
+    }
+
+    if !conflicts.is_empty() {
+        bail!(
+            "These robots differ from robotrumble.org, where they may have changed since the last \
+             sync, so they weren't overwritten: {}. Check the changes with `account diff`, then \
+             pass --force to overwrite them with the local code",
+            conflicts.join(", ")
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{decide, Action};
+
+    #[test]
+    fn unchanged() {
+        assert_eq!(
+            decide("a", Some("a"), Some("a"), true, false),
+            Action::UpToDate
+        );
+        // the same change was made on both sides
+        assert_eq!(
+            decide("b", Some("b"), Some("a"), true, false),
+            Action::UpToDate
+        );
+        assert_eq!(decide("a", Some("a"), None, false, false), Action::UpToDate);
+    }
+
+    #[test]
+    fn changed_locally() {
+        assert_eq!(decide("b", Some("a"), Some("a"), true, false), Action::Push);
+        assert_eq!(
+            decide("b", Some("a"), Some("a"), false, false),
+            Action::Push
+        );
+    }
+
+    #[test]
+    fn changed_on_the_site() {
+        assert_eq!(decide("a", Some("b"), Some("a"), true, false), Action::Pull);
+        assert_eq!(decide("a", Some("b"), Some("a"), true, true), Action::Pull);
+        // bundled code can't be pulled, so the site's change isn't lost either
+        assert_eq!(
+            decide("a", Some("b"), Some("a"), false, false),
+            Action::Conflict
+        );
+        assert_eq!(decide("a", Some("b"), Some("a"), false, true), Action::Push);
+    }
+
+    #[test]
+    fn changed_on_both_sides() {
+        assert_eq!(
+            decide("b", Some("c"), Some("a"), true, false),
+            Action::Conflict
+        );
+        assert_eq!(decide("b", Some("c"), Some("a"), true, true), Action::Push);
+    }
+
+    #[test]
+    fn never_synced() {
+        assert_eq!(decide("a", Some("b"), None, true, false), Action::Conflict);
+        assert_eq!(decide("a", Some("b"), None, true, true), Action::Push);
+    }
+
+    #[test]
+    fn no_code_on_the_site() {
+        assert_eq!(decide("a", None, None, true, false), Action::Push);
+        assert_eq!(decide("a", None, Some("a"), true, false), Action::Push);
+    }
+}
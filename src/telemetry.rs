@@ -0,0 +1,189 @@
+//! Crash reporting, which can be turned off with `--no-telemetry` or `telemetry = false` in the
+//! config, and debug bundles, which are written locally for attaching to bug reports by hand.
+//! Anything that leaves the machine is scrubbed of the auth key and local paths first, and a
+//! debug bundle only keeps the names of the environment variables passed to robots.
+
+use anyhow::Context;
+use logic::MainOutput;
+use serde_json::{json, Value};
+use std::fs::{self, File};
+use std::path::Path;
+use std::sync::Arc;
+
+/// Replaces secrets and local paths in text
+struct Scrubber {
+    replacements: Vec<(String, &'static str)>,
+}
+
+impl Scrubber {
+    fn new() -> Self {
+        let mut replacements = Vec::new();
+        if let Some(auth_key) = super::CONFIG.get().and_then(|c| c.auth_key()) {
+            replacements.push((auth_key.into_owned(), "[auth_key]"));
+        }
+        if let Ok(Some(token)) = super::api::api_token() {
+            replacements.push((token.to_owned(), "[api_token]"));
+        }
+        // the current directory is usually inside the home directory, so it goes first
+        if let Ok(cwd) = std::env::current_dir() {
+            replacements.push((cwd.display().to_string(), "."));
+        }
+        if let Some(dirs) = directories::BaseDirs::new() {
+            replacements.push((dirs.home_dir().display().to_string(), "~"));
+        }
+        replacements.retain(|(from, _)| from.len() > 1);
+        Self { replacements }
+    }
+
+    fn scrub(&self, s: &str) -> String {
+        self.replacements
+            .iter()
+            .fold(s.to_owned(), |s, (from, to)| s.replace(from.as_str(), to))
+    }
+
+    fn scrub_value(&self, value: &mut Value) {
+        match value {
+            Value::String(s) => *s = self.scrub(s),
+            Value::Array(values) => values.iter_mut().for_each(|v| self.scrub_value(v)),
+            Value::Object(map) => map.values_mut().for_each(|v| self.scrub_value(v)),
+            _ => {}
+        }
+    }
+}
+
+/// Start reporting crashes, if rumblebot was built with a `SENTRY_DSN`
+pub fn init() -> Option<sentry::ClientInitGuard> {
+    let dsn = option_env!("SENTRY_DSN")?;
+    let options = sentry::ClientOptions {
+        release: sentry::release_name!(),
+        send_default_pii: false,
+        before_send: Some(Arc::new(scrub_event)),
+        ..Default::default()
+    };
+    Some(sentry::init((dsn, options)))
+}
+
+/// Go through every string in the event, since paths can end up anywhere from the message to the
+/// stack trace. If that doesn't work for some reason, the event isn't sent at all
+fn scrub_event(
+    event: sentry::protocol::Event<'static>,
+) -> Option<sentry::protocol::Event<'static>> {
+    let mut value = serde_json::to_value(&event).ok()?;
+    Scrubber::new().scrub_value(&mut value);
+    let mut event: sentry::protocol::Event<'static> = serde_json::from_value(value).ok()?;
+    event.server_name = None;
+    Some(event)
+}
+
+/// A seed for a game, so that a debug bundle always has what's needed to replay it
+pub fn random_seed() -> String {
+    use rand::distributions::Alphanumeric;
+    use rand::Rng;
+    rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(16)
+        .map(char::from)
+        .collect()
+}
+
+/// Robots' environment variables are often where their secrets go, so only the names are kept
+const REDACTED: &str = "[redacted]";
+
+fn redact_env(spec: &Value) -> Value {
+    let mut spec = spec.clone();
+    for key in &["blue_env", "red_env"] {
+        if let Some(Value::Object(env)) = spec.get_mut(*key) {
+            env.values_mut()
+                .for_each(|value| *value = Value::from(REDACTED));
+        }
+    }
+    spec
+}
+
+/// The values of `--blue-env KEY=VALUE` and `--red-env KEY=VALUE`, in either form
+fn redact_env_args(args: impl IntoIterator<Item = String>) -> Vec<String> {
+    let redact = |var: &str| match var.split_once('=') {
+        Some((key, _)) => format!("{}={}", key, REDACTED),
+        None => var.to_owned(),
+    };
+    let mut redact_next = false;
+    args.into_iter()
+        .map(|arg| {
+            if std::mem::take(&mut redact_next) {
+                return redact(&arg);
+            }
+            match arg.split_once('=') {
+                Some((flag @ ("--blue-env" | "--red-env"), var)) => {
+                    format!("{}={}", flag, redact(var))
+                }
+                _ => {
+                    redact_next = arg == "--blue-env" || arg == "--red-env";
+                    arg
+                }
+            }
+        })
+        .collect()
+}
+
+/// Write a `.tar.gz` with everything needed to look into a problem with a game: the command line,
+/// the version of rumblebot, the game spec, how the game ended, and the robots' logs from
+/// `log_dir`
+pub fn write_debug_bundle(
+    path: &Path,
+    spec: &Value,
+    result: Result<&MainOutput, &anyhow::Error>,
+    log_dir: Option<&Path>,
+) -> anyhow::Result<()> {
+    let scrubber = Scrubber::new();
+    let file = File::create(path).with_context(|| format!("Couldn't create {}", path.display()))?;
+    let gz = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+    let mut tar = tar::Builder::new(gz);
+    let mut add = |name: &str, contents: String| -> anyhow::Result<()> {
+        let contents = scrubber.scrub(&contents);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(contents.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        tar.append_data(&mut header, name, contents.as_bytes())?;
+        Ok(())
+    };
+
+    let args = redact_env_args(std::env::args());
+    add("command.txt", shell_words::join(args) + "\n")?;
+    add(
+        "version.txt",
+        format!(
+            "rumblebot {}\n{} {}\n",
+            env!("CARGO_PKG_VERSION"),
+            std::env::consts::OS,
+            std::env::consts::ARCH
+        ),
+    )?;
+    add(
+        "spec.json",
+        serde_json::to_string_pretty(&redact_env(spec))?,
+    )?;
+    let result = match result {
+        Ok(output) => json!({ "winner": output.winner, "errors": output.errors }),
+        Err(err) => json!({ "error": format!("{:#}", err) }),
+    };
+    add("result.json", serde_json::to_string_pretty(&result)?)?;
+    // only the logs we wrote, since --log-dir could be a directory with anything else in it
+    if let Some(log_dir) = log_dir {
+        for name in &["blue.log", "red.log"] {
+            let path = log_dir.join(name);
+            match fs::read_to_string(&path) {
+                Ok(log) => add(&format!("logs/{}", name), log)?,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => {
+                    return Err(
+                        anyhow::Error::new(e).context(format!("Couldn't read {}", path.display()))
+                    )
+                }
+            }
+        }
+    }
+
+    tar.into_inner()?.finish()?;
+    Ok(())
+}
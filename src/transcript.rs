@@ -0,0 +1,94 @@
+//! Transcripts of everything a robot was sent and answered during a game, written with
+//! `--record-io`, and "ghost" robots that replay them.
+//!
+//! A transcript is a file with one JSON object per line, one for each turn:
+//! `{"turn": $TURN, "input": $PROGRAM_INPUT, "output": $PROGRAM_RESULT}`
+
+use anyhow::Context;
+use logic::{ProgramOutput, ProgramResult};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+#[derive(Serialize)]
+struct RecordedTurn<'a> {
+    turn: usize,
+    input: &'a serde_json::Value,
+    output: &'a ProgramResult,
+}
+
+/// Writes a robot's transcript as it plays
+pub struct Recorder {
+    file: BufWriter<File>,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> anyhow::Result<Self> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("Couldn't create directory {}", dir.display()))?;
+        }
+        let file =
+            File::create(path).with_context(|| format!("Couldn't create {}", path.display()))?;
+        Ok(Self {
+            file: BufWriter::new(file),
+        })
+    }
+
+    /// `input` is already serialized, since the `ProgramInput` is handed off to the robot before
+    /// its output comes back
+    pub fn record(
+        &mut self,
+        turn: usize,
+        input: &serde_json::Value,
+        output: &ProgramResult,
+    ) -> io::Result<()> {
+        let line = RecordedTurn {
+            turn,
+            input,
+            output,
+        };
+        serde_json::to_writer(&mut self.file, &line)?;
+        self.file.write_all(b"\n")?;
+        // keep the transcript complete even if the game is cut short
+        self.file.flush()
+    }
+}
+
+#[derive(Deserialize)]
+struct ReplayedTurn {
+    turn: usize,
+    output: ProgramResult,
+}
+
+/// A robot that answers with what was recorded in a transcript for each turn, and does nothing
+/// on turns that weren't recorded
+pub struct Ghost {
+    outputs: HashMap<usize, ProgramResult>,
+}
+
+impl Ghost {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let transcript = fs::read_to_string(path)
+            .with_context(|| format!("Couldn't read transcript {}", path.display()))?;
+        let mut outputs = HashMap::new();
+        for (i, line) in transcript.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let ReplayedTurn { turn, output } = serde_json::from_str(line).with_context(|| {
+                format!("Invalid transcript line {} in {}", i + 1, path.display())
+            })?;
+            outputs.insert(turn, output);
+        }
+        Ok(Self { outputs })
+    }
+
+    pub fn output(&mut self, turn: usize) -> ProgramResult {
+        self.outputs
+            .remove(&turn)
+            .unwrap_or_else(|| Ok(ProgramOutput::default()))
+    }
+}
@@ -0,0 +1,542 @@
+//! `run tune`: searching for the parameters that a robot reads from its environment variables
+//! that win it the most games
+
+use anyhow::{bail, Context};
+use logic::{GameMode, Team};
+use rand::rngs::StdRng;
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use super::command::CommandLimits;
+use super::{run_game, GameSpec, RunOptions};
+
+/// How many points to try for a float parameter without a `step` in a grid search
+const DEFAULT_FLOAT_STEPS: usize = 5;
+
+/// The most combinations a grid search will try, since it's easy to write a space with far more
+/// than could ever be played
+const MAX_GRID_SIZE: u128 = 100_000;
+
+/// The parameter space, loaded from a TOML file like:
+///
+/// ```toml
+/// [params.AGGRESSION]
+/// min = 0.0
+/// max = 1.0
+/// step = 0.25
+///
+/// [params.RADIUS]
+/// min = 1
+/// max = 5
+///
+/// [params.OPENING]
+/// choices = ["rush", "turtle"]
+/// ```
+#[derive(Deserialize)]
+pub struct Space {
+    params: BTreeMap<String, Param>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Param {
+    Choices {
+        choices: Vec<toml::Value>,
+    },
+    Int {
+        min: i64,
+        max: i64,
+        step: Option<i64>,
+    },
+    Float {
+        min: f64,
+        max: f64,
+        step: Option<f64>,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Value {
+    Int(i64),
+    Float(f64),
+    Choice(usize),
+}
+
+/// A value for every parameter, in the same order as `Space::params`
+type Candidate = Vec<Value>;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, strum::EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum Strategy {
+    /// Every combination of parameters
+    Grid,
+    /// Parameters picked at random
+    Random,
+    /// Start with random parameters, then keep the best half of each generation and fill the
+    /// rest with mutations of them
+    Evolve,
+}
+
+pub struct TuneOptions {
+    pub robot: String,
+    pub opponents: Vec<String>,
+    /// How many games to play against each opponent
+    pub seeds: usize,
+    pub strategy: Strategy,
+    /// The most candidates to evaluate with the random and evolutionary strategies
+    pub budget: usize,
+    /// The size of each generation for the evolutionary strategy
+    pub population: usize,
+    pub turn_num: usize,
+    pub game_mode: GameMode,
+    pub rng_seed: Option<u64>,
+    /// Where to write the leaderboard as JSON, updated after every candidate
+    pub leaderboard: Option<PathBuf>,
+    pub limits: CommandLimits,
+}
+
+#[derive(Serialize, Clone)]
+pub struct Entry {
+    pub params: BTreeMap<String, String>,
+    pub win_rate: f64,
+    pub wins: usize,
+    pub ties: usize,
+    pub losses: usize,
+}
+
+impl Space {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let s = fs::read_to_string(path)
+            .with_context(|| format!("Couldn't read {}", path.display()))?;
+        let space: Self =
+            toml::from_str(&s).with_context(|| format!("Invalid {}", path.display()))?;
+        if space.params.is_empty() {
+            bail!("{} doesn't have any [params]", path.display());
+        }
+        for (name, param) in &space.params {
+            let valid = match param {
+                Param::Choices { choices } => !choices.is_empty(),
+                Param::Int { min, max, step } => min <= max && step.map_or(true, |s| s > 0),
+                Param::Float { min, max, step } => {
+                    min <= max && step.map_or(true, |s| s > 0.0 && s.is_finite())
+                }
+            };
+            if !valid {
+                bail!(
+                    "invalid parameter {} in {}: it needs a non-empty list of `choices`, or a \
+                    `min` that's at most its `max` and a positive `step`",
+                    name,
+                    path.display()
+                );
+            }
+        }
+        Ok(space)
+    }
+
+    fn env(&self, candidate: &[Value]) -> BTreeMap<String, String> {
+        self.params
+            .iter()
+            .zip(candidate)
+            .map(|((name, param), value)| {
+                let value = match (param, value) {
+                    (Param::Choices { choices }, Value::Choice(i)) => match &choices[*i] {
+                        toml::Value::String(s) => s.clone(),
+                        value => value.to_string(),
+                    },
+                    (_, Value::Int(i)) => i.to_string(),
+                    (_, Value::Float(f)) => format_float(*f),
+                    _ => unreachable!(),
+                };
+                (name.clone(), value)
+            })
+            .collect()
+    }
+
+    /// How many combinations `grid` has, worked out without making them
+    fn grid_size(&self) -> u128 {
+        self.params
+            .values()
+            .map(|param| match *param {
+                Param::Choices { ref choices } => choices.len() as u128,
+                Param::Int { min, max, step } => {
+                    ((max as i128 - min as i128) / step.unwrap_or(1) as i128) as u128 + 1
+                }
+                Param::Float { min, max, step } => float_steps(min, max, step) as u128 + 1,
+            })
+            .fold(1, u128::saturating_mul)
+    }
+
+    fn grid(&self) -> anyhow::Result<Vec<Candidate>> {
+        let size = self.grid_size();
+        if size > MAX_GRID_SIZE {
+            bail!(
+                "a grid search would try {} combinations, and the most it can is {}; use bigger \
+                `step`s, or the random or evolve strategy",
+                size,
+                MAX_GRID_SIZE
+            );
+        }
+        let axes = self.params.values().map(|param| match *param {
+            Param::Choices { ref choices } => (0..choices.len()).map(Value::Choice).collect(),
+            Param::Int { min, max, step } => (min..=max)
+                .step_by(step.unwrap_or(1) as usize)
+                .map(Value::Int)
+                .collect(),
+            Param::Float { min, max, step } => {
+                let steps = float_steps(min, max, step);
+                let step = step.unwrap_or((max - min) / steps as f64);
+                (0..=steps)
+                    .map(|i| Value::Float(min + step * i as f64))
+                    .collect::<Vec<_>>()
+            }
+        });
+        Ok(axes.fold(vec![Vec::new()], |candidates, axis: Vec<Value>| {
+            candidates
+                .iter()
+                .flat_map(|candidate| {
+                    axis.iter().map(move |value| {
+                        let mut candidate = candidate.clone();
+                        candidate.push(*value);
+                        candidate
+                    })
+                })
+                .collect()
+        }))
+    }
+
+    fn sample(&self, rng: &mut StdRng) -> Candidate {
+        self.params
+            .values()
+            .map(|param| match *param {
+                Param::Choices { ref choices } => Value::Choice(rng.gen_range(0..choices.len())),
+                Param::Int { min, max, .. } => Value::Int(rng.gen_range(min..=max)),
+                Param::Float { min, max, .. } => Value::Float(rng.gen_range(min..=max)),
+            })
+            .collect()
+    }
+
+    /// Change each parameter with a 50% chance, nudging numbers by up to a fifth of their range
+    fn mutate(&self, candidate: &[Value], rng: &mut StdRng) -> Candidate {
+        self.params
+            .values()
+            .zip(candidate)
+            .map(|(param, &value)| {
+                if rng.gen_bool(0.5) {
+                    return value;
+                }
+                match (param, value) {
+                    (Param::Choices { choices }, _) => {
+                        Value::Choice(rng.gen_range(0..choices.len()))
+                    }
+                    (&Param::Int { min, max, .. }, Value::Int(i)) => {
+                        let delta = ((max - min) / 5).max(1);
+                        Value::Int((i + rng.gen_range(-delta..=delta)).clamp(min, max))
+                    }
+                    (&Param::Float { min, max, .. }, Value::Float(f)) => {
+                        let delta = (max - min) / 5.0;
+                        Value::Float((f + rng.gen_range(-delta..=delta)).clamp(min, max))
+                    }
+                    _ => unreachable!(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// How many steps there are from `min` to `max` for a float parameter in a grid search
+fn float_steps(min: f64, max: f64, step: Option<f64>) -> usize {
+    match step {
+        // saturating, for a step that's tiny next to the range
+        Some(step) => ((max - min) / step + 1e-9).floor() as usize,
+        None => DEFAULT_FLOAT_STEPS - 1,
+    }
+}
+
+/// Floats are passed to the robot rounded, so that e.g. `0.1 * 3` is `0.3`
+fn format_float(f: f64) -> String {
+    let s = format!("{:.6}", f);
+    let s = s.trim_end_matches('0');
+    let s = s
+        .strip_suffix('.')
+        .map_or_else(|| s.to_owned(), |s| format!("{}.0", s));
+    if s == "-0.0" {
+        "0.0".to_owned()
+    } else {
+        s
+    }
+}
+
+struct Tuner<'a> {
+    space: &'a Space,
+    opts: &'a TuneOptions,
+    /// Every candidate that's been evaluated, keyed by its environment variables, since the
+    /// evolutionary strategy can come up with the same one more than once
+    results: BTreeMap<BTreeMap<String, String>, Entry>,
+}
+
+impl Tuner<'_> {
+    /// Play the candidate against every opponent, returning its win rate
+    async fn evaluate(&mut self, candidate: &[Value]) -> anyhow::Result<f64> {
+        let env = self.space.env(candidate);
+        if let Some(entry) = self.results.get(&env) {
+            return Ok(entry.win_rate);
+        }
+
+        let mut entry = Entry {
+            params: env.clone(),
+            win_rate: 0.0,
+            wins: 0,
+            ties: 0,
+            losses: 0,
+        };
+        for opponent in &self.opts.opponents {
+            for i in 0..self.opts.seeds {
+                // play each seed from both sides, in case one of them has an advantage
+                let team = if i % 2 == 0 { Team::Blue } else { Team::Red };
+                let (blue, red) = match team {
+                    Team::Blue => (&self.opts.robot, opponent),
+                    Team::Red => (opponent, &self.opts.robot),
+                };
+                let mut spec = GameSpec {
+                    blue: blue.clone(),
+                    red: red.clone(),
+                    seed: Some(format!("tune-{}", i / 2)),
+                    turn_num: Some(self.opts.turn_num),
+                    expect_winner: None,
+                    game_mode: None,
+                    blue_env: BTreeMap::new(),
+                    red_env: BTreeMap::new(),
+                };
+                match team {
+                    Team::Blue => spec.blue_env = env.clone(),
+                    Team::Red => spec.red_env = env.clone(),
+                }
+                let run_opts = RunOptions {
+                    limits: self.opts.limits.clone(),
+                    ..Default::default()
+                };
+                let output = run_game(spec, self.opts.game_mode, &run_opts, |_| {}).await?;
+                match output.winner {
+                    Some(winner) if winner == team => entry.wins += 1,
+                    Some(_) => entry.losses += 1,
+                    None => entry.ties += 1,
+                }
+            }
+        }
+        let games = entry.wins + entry.ties + entry.losses;
+        entry.win_rate = (entry.wins as f64 + entry.ties as f64 / 2.0) / games as f64;
+
+        eprintln!(
+            "[tune {}] {} -> {:.1}%",
+            self.results.len() + 1,
+            format_params(&entry.params),
+            entry.win_rate * 100.0
+        );
+        let win_rate = entry.win_rate;
+        self.results.insert(env, entry);
+        if let Some(path) = &self.opts.leaderboard {
+            write_leaderboard(path, &self.leaderboard())?;
+        }
+        Ok(win_rate)
+    }
+
+    fn leaderboard(&self) -> Vec<Entry> {
+        let mut entries: Vec<Entry> = self.results.values().cloned().collect();
+        entries.sort_by(|a, b| b.win_rate.total_cmp(&a.win_rate));
+        entries
+    }
+}
+
+/// Search the parameter space, returning the leaderboard of every candidate that was tried
+pub async fn tune(space: &Space, opts: &TuneOptions) -> anyhow::Result<Vec<Entry>> {
+    if opts.opponents.is_empty() || opts.seeds == 0 {
+        bail!("there must be at least one opponent and one seed to play against");
+    }
+    let mut rng = match opts.rng_seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    };
+    let mut tuner = Tuner {
+        space,
+        opts,
+        results: BTreeMap::new(),
+    };
+
+    match opts.strategy {
+        Strategy::Grid => {
+            let grid = space.grid()?;
+            eprintln!("[tune] trying all {} combinations", grid.len());
+            for candidate in &grid {
+                tuner.evaluate(candidate).await?;
+            }
+        }
+        Strategy::Random => {
+            for _ in 0..opts.budget {
+                let candidate = space.sample(&mut rng);
+                tuner.evaluate(&candidate).await?;
+            }
+        }
+        Strategy::Evolve => {
+            let population_size = opts.population.max(2);
+            let mut population: Vec<Candidate> = (0..population_size)
+                .map(|_| space.sample(&mut rng))
+                .collect();
+            loop {
+                let tried = tuner.results.len();
+                let mut scored = Vec::new();
+                for candidate in population {
+                    if tuner.results.len() >= opts.budget {
+                        break;
+                    }
+                    let score = tuner.evaluate(&candidate).await?;
+                    scored.push((score, candidate));
+                }
+                // stop once the budget is spent, or if there's nothing new left to try
+                if tuner.results.len() >= opts.budget || tuner.results.len() == tried {
+                    break;
+                }
+                scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+                scored.truncate((population_size / 2).max(1));
+                let parents: Vec<Candidate> = scored.into_iter().map(|(_, c)| c).collect();
+                population = parents.clone();
+                while population.len() < population_size {
+                    let parent = parents.choose(&mut rng).unwrap();
+                    population.push(space.mutate(parent, &mut rng));
+                }
+            }
+        }
+    }
+
+    Ok(tuner.leaderboard())
+}
+
+pub fn format_params(params: &BTreeMap<String, String>) -> String {
+    params
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, v))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn write_leaderboard(path: &Path, entries: &[Entry]) -> anyhow::Result<()> {
+    let json = serde_json::to_string_pretty(entries).unwrap();
+    fs::write(path, json).with_context(|| format!("Couldn't write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn space(toml: &str) -> Space {
+        toml::from_str(toml).unwrap()
+    }
+
+    fn envs(space: &Space, candidates: &[Candidate]) -> Vec<String> {
+        candidates
+            .iter()
+            .map(|candidate| format_params(&space.env(candidate)))
+            .collect()
+    }
+
+    #[test]
+    fn grid_goes_through_the_last_parameter_first() {
+        let space = space(
+            r#"
+            [params.B]
+            min = 1
+            max = 5
+            step = 2
+
+            [params.A]
+            choices = ["rush", 2]
+            "#,
+        );
+        assert_eq!(space.grid_size(), 6);
+        let grid = space.grid().unwrap();
+        assert_eq!(
+            envs(&space, &grid),
+            [
+                "A=rush B=1",
+                "A=rush B=3",
+                "A=rush B=5",
+                "A=2 B=1",
+                "A=2 B=3",
+                "A=2 B=5",
+            ]
+        );
+    }
+
+    #[test]
+    fn grid_steps_floats() {
+        let stepped = space("[params.X]\nmin = 0.0\nmax = 1.0\nstep = 0.3");
+        let grid = stepped.grid().unwrap();
+        assert_eq!(envs(&stepped, &grid), ["X=0.0", "X=0.3", "X=0.6", "X=0.9"]);
+
+        // a step that divides the range exactly still reaches the max
+        let exact = space("[params.X]\nmin = 0.0\nmax = 0.3\nstep = 0.1");
+        let grid = exact.grid().unwrap();
+        assert_eq!(envs(&exact, &grid), ["X=0.0", "X=0.1", "X=0.2", "X=0.3"]);
+
+        let unstepped = space("[params.X]\nmin = -1.0\nmax = 1.0");
+        let grid = unstepped.grid().unwrap();
+        assert_eq!(
+            envs(&unstepped, &grid),
+            ["X=-1.0", "X=-0.5", "X=0.0", "X=0.5", "X=1.0"]
+        );
+    }
+
+    #[test]
+    fn grid_is_capped() {
+        let huge = space("[params.X]\nmin = -9223372036854775808\nmax = 9223372036854775807");
+        assert!(huge.grid().is_err());
+        let tiny_step = space("[params.X]\nmin = 0.0\nmax = 1.0\nstep = 1e-300");
+        assert!(tiny_step.grid().is_err());
+        let many = space("[params.A]\nmin = 1\nmax = 1000\n[params.B]\nmin = 1\nmax = 1000");
+        assert_eq!(many.grid_size(), 1_000_000);
+        assert!(many.grid().is_err());
+    }
+
+    #[test]
+    fn mutations_stay_in_range() {
+        let space = space(
+            r#"
+            [params.F]
+            min = 0.0
+            max = 1.0
+
+            [params.I]
+            min = -3
+            max = 3
+            "#,
+        );
+        let mut rng = StdRng::seed_from_u64(0);
+        for edge in &[
+            [Value::Float(0.0), Value::Int(-3)],
+            [Value::Float(1.0), Value::Int(3)],
+        ] {
+            for _ in 0..100 {
+                match space.mutate(edge, &mut rng)[..] {
+                    [Value::Float(f), Value::Int(i)] => {
+                        assert!((0.0..=1.0).contains(&f), "{}", f);
+                        assert!((-3..=3).contains(&i), "{}", i);
+                    }
+                    _ => panic!("mutating changed the kinds of values"),
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn formats_floats() {
+        assert_eq!(format_float(0.1 * 3.0), "0.3");
+        assert_eq!(format_float(2.0), "2.0");
+        assert_eq!(format_float(-1.5), "-1.5");
+        assert_eq!(format_float(-0.0), "0.0");
+        // rounds to "-0.0"
+        assert_eq!(format_float(-0.0000001), "0.0");
+    }
+}
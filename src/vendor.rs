@@ -0,0 +1,443 @@
+//! Third-party packages for robots, which are made available to the robot read-only under
+//! `/vendor`. Python packages go straight in it (so it works as a `sys.path` entry), and JS packages
+//! go in `/vendor/$PACKAGE_NAME` (so it works for module resolution). Once packages are unpacked,
+//! they're cached by a hash of what went into them, so that they aren't unpacked for every game.
+
+use anyhow::{anyhow, bail, Context};
+use sha2::{Digest, Sha256};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use super::manifest::Manifest;
+
+/// The directory in a robot directory that holds its packages
+pub const VENDOR_DIR: &str = "vendor";
+
+/// Where the packages are mounted for the robot
+pub const WASI_VENDOR_DIR: &str = "/vendor";
+
+/// Files that mean a package needs native code, which can't run in the wasm sandbox
+const NATIVE_EXTENSIONS: &[&str] = &["so", "pyd", "dylib", "dll", "node"];
+
+pub struct VendorDir {
+    pub path: PathBuf,
+    /// If there were any archives and the cache couldn't be used, they're unpacked into a
+    /// temporary directory that we need to keep open
+    _tempdir: Option<tempfile::TempDir>,
+}
+
+enum Archive {
+    /// A Python `.whl`
+    Wheel,
+    /// An npm `.tgz`, like what `npm pack` creates
+    NpmTarball,
+}
+
+impl Archive {
+    fn kind(path: &Path) -> Option<Self> {
+        let name = path.file_name()?.to_str()?;
+        if name.ends_with(".whl") {
+            Some(Self::Wheel)
+        } else if name.ends_with(".tgz") || name.ends_with(".tar.gz") {
+            Some(Self::NpmTarball)
+        } else {
+            None
+        }
+    }
+}
+
+/// Gather the packages from a robot directory's `vendor/` and the `vendor` list in its manifest
+pub fn prepare(robot_dir: &Path, manifest: &Manifest) -> anyhow::Result<Option<VendorDir>> {
+    let cache = super::directories()
+        .ok()
+        .map(|dirs| dirs.cache_dir().join("vendor"))
+        .filter(|cache| fs::create_dir_all(cache).is_ok());
+    prepare_with_cache(robot_dir, manifest, cache.as_deref())
+}
+
+fn prepare_with_cache(
+    robot_dir: &Path,
+    manifest: &Manifest,
+    cache: Option<&Path>,
+) -> anyhow::Result<Option<VendorDir>> {
+    let vendor_dir = robot_dir.join(VENDOR_DIR);
+
+    let mut archives = manifest
+        .vendor
+        .iter()
+        .map(|path| robot_dir.join(path))
+        .collect::<Vec<_>>();
+    let mut plain = Vec::new();
+    if vendor_dir.is_dir() {
+        let mut entries = fs::read_dir(&vendor_dir)
+            .and_then(|entries| {
+                entries
+                    .map(|entry| Ok(entry?.path()))
+                    .collect::<io::Result<Vec<_>>>()
+            })
+            .with_context(|| format!("Couldn't read {}", vendor_dir.display()))?;
+        // so that they're unpacked and hashed the same way every time
+        entries.sort();
+        for path in entries {
+            match Archive::kind(&path) {
+                Some(_) => archives.push(path),
+                None => plain.push(path),
+            }
+        }
+    }
+
+    for path in &plain {
+        check_pure(path)?;
+    }
+
+    if archives.is_empty() {
+        if plain.is_empty() {
+            return Ok(None);
+        }
+        // nothing to unpack, so the directory can be used as is
+        return Ok(Some(VendorDir {
+            path: vendor_dir,
+            _tempdir: None,
+        }));
+    }
+
+    let cache = match cache {
+        Some(cache) => cache,
+        None => {
+            let tempdir = tempfile::tempdir().context("couldn't create temporary directory")?;
+            unpack_all(&plain, &archives, tempdir.path())?;
+            return Ok(Some(VendorDir {
+                path: tempdir.path().to_owned(),
+                _tempdir: Some(tempdir),
+            }));
+        }
+    };
+
+    let mut hasher = Sha256::new();
+    for path in plain.iter().chain(&archives) {
+        hash_path(&mut hasher, path)
+            .with_context(|| format!("Couldn't read {}", path.display()))?;
+    }
+    let cached = cache.join(format!("{:x}", hasher.finalize()));
+    if !cached.is_dir() {
+        // unpacked next to where it'll end up, so that a half-unpacked directory is never used
+        let staging = tempfile::tempdir_in(cache).context("couldn't create temporary directory")?;
+        unpack_all(&plain, &archives, staging.path())?;
+        match fs::rename(staging.path(), &cached) {
+            Ok(()) => drop(staging.into_path()),
+            // another game unpacked the same packages first
+            Err(_) if cached.is_dir() => {}
+            Err(err) => {
+                return Err(anyhow::Error::new(err)
+                    .context(format!("Couldn't cache packages in {}", cached.display())))
+            }
+        }
+    }
+    Ok(Some(VendorDir {
+        path: cached,
+        _tempdir: None,
+    }))
+}
+
+fn unpack_all(plain: &[PathBuf], archives: &[PathBuf], dest: &Path) -> anyhow::Result<()> {
+    for path in plain {
+        copy_recursive(path, &dest.join(path.file_name().unwrap()))?;
+    }
+    for archive in archives {
+        let res = match Archive::kind(archive) {
+            Some(Archive::Wheel) => unpack_wheel(archive, dest),
+            Some(Archive::NpmTarball) => unpack_npm_tarball(archive, dest),
+            None => Err(anyhow!("it must be a .whl or a .tgz file")),
+        };
+        res.with_context(|| format!("Couldn't vendor {}", archive.display()))?;
+    }
+    Ok(())
+}
+
+/// Hash the name and contents of a package, so that changing anything in it gets it unpacked again
+fn hash_path(hasher: &mut Sha256, path: &Path) -> io::Result<()> {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    hasher.update((name.len() as u64).to_le_bytes());
+    hasher.update(name.as_bytes());
+    if path.is_dir() {
+        let mut entries = fs::read_dir(path)?
+            .map(|entry| Ok(entry?.path()))
+            .collect::<io::Result<Vec<_>>>()?;
+        entries.sort();
+        hasher.update(b"d");
+        hasher.update((entries.len() as u64).to_le_bytes());
+        for entry in &entries {
+            hash_path(hasher, entry)?;
+        }
+    } else {
+        let contents = fs::read(path)?;
+        hasher.update(b"f");
+        hasher.update((contents.len() as u64).to_le_bytes());
+        hasher.update(&contents);
+    }
+    Ok(())
+}
+
+fn native_error(package: &Path, file: &Path) -> anyhow::Error {
+    anyhow!(
+        "{} needs native extensions ({}), which can't run in the robot sandbox; only pure \
+        Python or JavaScript packages are supported",
+        package.display(),
+        file.display()
+    )
+}
+
+fn is_native(path: &Path) -> bool {
+    path.file_name().map_or(false, |name| name == "binding.gyp")
+        || path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map_or(false, |ext| NATIVE_EXTENSIONS.contains(&ext))
+}
+
+/// Make sure that a package doesn't have any native code in it
+fn check_pure(path: &Path) -> anyhow::Result<()> {
+    match find_native(path)? {
+        Some(file) => Err(native_error(path, &file)),
+        None => Ok(()),
+    }
+}
+
+fn find_native(path: &Path) -> anyhow::Result<Option<PathBuf>> {
+    if is_native(path) {
+        return Ok(Some(path.to_owned()));
+    }
+    if path.is_dir() {
+        for entry in fs::read_dir(path)? {
+            if let Some(file) = find_native(&entry?.path())? {
+                return Ok(Some(file));
+            }
+        }
+    }
+    Ok(None)
+}
+
+fn copy_recursive(from: &Path, to: &Path) -> anyhow::Result<()> {
+    if from.is_dir() {
+        fs::create_dir_all(to)?;
+        for entry in fs::read_dir(from)? {
+            let entry = entry?;
+            copy_recursive(&entry.path(), &to.join(entry.file_name()))?;
+        }
+    } else {
+        fs::copy(from, to)
+            .with_context(|| format!("Couldn't copy {} to {}", from.display(), to.display()))?;
+    }
+    Ok(())
+}
+
+fn unpack_wheel(path: &Path, dest: &Path) -> anyhow::Result<()> {
+    // wheel file names are `{name}-{version}(-{build})?-{python}-{abi}-{platform}.whl`
+    let platform = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .and_then(|s| s.rsplit('-').next())
+        .unwrap_or("");
+    if platform != "any" {
+        bail!(
+            "{} is built for the {:?} platform, so it needs native extensions, which can't run in \
+            the robot sandbox; only pure Python packages (with the \"any\" platform tag) are supported",
+            path.display(),
+            platform
+        );
+    }
+
+    let mut zip = zip::ZipArchive::new(File::open(path)?)?;
+    if let Some(file) = zip.file_names().map(Path::new).find(|f| is_native(f)) {
+        return Err(native_error(path, file));
+    }
+    zip.extract(dest)?;
+    Ok(())
+}
+
+fn unpack_npm_tarball(path: &Path, dest: &Path) -> anyhow::Result<()> {
+    // unpack it next to where it'll end up, so that it can just be renamed into place
+    let staging = tempfile::tempdir_in(dest)?;
+    let gz = flate2::read::GzDecoder::new(File::open(path)?);
+    tar::Archive::new(gz).unpack(staging.path())?;
+
+    // npm puts everything in a `package` directory
+    let package = staging.path().join("package");
+    let package_json = fs::read_to_string(package.join("package.json"))
+        .context("Couldn't read package/package.json; is this an npm package?")?;
+    #[derive(serde::Deserialize)]
+    struct PackageJson {
+        name: String,
+    }
+    let PackageJson { name } =
+        serde_json::from_str(&package_json).context("Invalid package/package.json")?;
+    let name_is_valid = !name.is_empty()
+        && name
+            .split('/')
+            .all(|part| !part.is_empty() && part != "." && part != "..");
+    if !name_is_valid {
+        bail!("invalid package name {:?}", name);
+    }
+
+    if let Some(file) = find_native(&package)? {
+        return Err(native_error(
+            path,
+            file.strip_prefix(&package).unwrap_or(&file),
+        ));
+    }
+
+    // scoped packages like `@scope/name` go in a subdirectory
+    let target = dest.join(&name);
+    fs::create_dir_all(target.parent().unwrap())?;
+    fs::rename(&package, &target)
+        .with_context(|| format!("Couldn't install {} to {}", name, target.display()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+
+    fn wheel(dir: &Path, name: &str, files: &[(&str, &str)]) {
+        let mut zip = zip::ZipWriter::new(File::create(dir.join(name)).unwrap());
+        for (path, contents) in files {
+            zip.start_file(*path, Default::default()).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap();
+    }
+
+    fn npm_tarball(dir: &Path, name: &str, files: &[(&str, &str)]) {
+        let gz = flate2::write::GzEncoder::new(
+            File::create(dir.join(name)).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut tar = tar::Builder::new(gz);
+        for (path, contents) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            tar.append_data(&mut header, path, contents.as_bytes())
+                .unwrap();
+        }
+        tar.into_inner().unwrap().finish().unwrap();
+    }
+
+    fn robot_dir() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir(dir.path().join(VENDOR_DIR)).unwrap();
+        dir
+    }
+
+    fn prepare(robot: &Path, cache: Option<&Path>) -> anyhow::Result<Option<VendorDir>> {
+        prepare_with_cache(robot, &Manifest::default(), cache)
+    }
+
+    #[test]
+    fn unpacks_wheels_and_npm_tarballs() {
+        let robot = robot_dir();
+        let vendor = robot.path().join(VENDOR_DIR);
+        wheel(
+            &vendor,
+            "pure-1.0-py3-none-any.whl",
+            &[("pure/__init__.py", "x = 1")],
+        );
+        npm_tarball(
+            &vendor,
+            "left-pad-1.0.0.tgz",
+            &[
+                ("package/package.json", r#"{"name": "@scope/left-pad"}"#),
+                ("package/index.js", "module.exports = 1"),
+            ],
+        );
+        fs::write(vendor.join("plain.py"), "y = 2").unwrap();
+
+        let vendor = prepare(robot.path(), None).unwrap().unwrap();
+        let read = |path: &str| fs::read_to_string(vendor.path.join(path)).unwrap();
+        assert_eq!(read("pure/__init__.py"), "x = 1");
+        assert_eq!(read("@scope/left-pad/index.js"), "module.exports = 1");
+        assert_eq!(read("plain.py"), "y = 2");
+    }
+
+    #[test]
+    fn uses_plain_packages_in_place() {
+        let robot = robot_dir();
+        fs::write(robot.path().join(VENDOR_DIR).join("plain.py"), "").unwrap();
+        let vendor = prepare(robot.path(), None).unwrap().unwrap();
+        assert_eq!(vendor.path, robot.path().join(VENDOR_DIR));
+
+        let empty = robot_dir();
+        assert!(prepare(empty.path(), None).unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_platform_wheels() {
+        let robot = robot_dir();
+        wheel(
+            &robot.path().join(VENDOR_DIR),
+            "fast-1.0-cp39-cp39-manylinux_2_17_x86_64.whl",
+            &[("fast/__init__.py", "")],
+        );
+        let err = prepare(robot.path(), None).err().unwrap();
+        assert!(format!("{:#}", err).contains("manylinux_2_17_x86_64"));
+    }
+
+    fn assert_native(res: anyhow::Result<Option<VendorDir>>) {
+        let err = format!("{:#}", res.err().unwrap());
+        assert!(err.contains("needs native extensions"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_native_extensions() {
+        let robot = robot_dir();
+        wheel(
+            &robot.path().join(VENDOR_DIR),
+            "sneaky-1.0-py3-none-any.whl",
+            &[("sneaky/_speedups.so", "")],
+        );
+        assert_native(prepare(robot.path(), None));
+
+        let robot = robot_dir();
+        npm_tarball(
+            &robot.path().join(VENDOR_DIR),
+            "addon-1.0.0.tgz",
+            &[
+                ("package/package.json", r#"{"name": "addon"}"#),
+                ("package/binding.gyp", "{}"),
+            ],
+        );
+        assert_native(prepare(robot.path(), None));
+
+        let robot = robot_dir();
+        let plain = robot.path().join(VENDOR_DIR).join("plain");
+        fs::create_dir_all(plain.join("lib")).unwrap();
+        fs::write(plain.join("lib").join("native.pyd"), "").unwrap();
+        assert_native(prepare(robot.path(), None));
+    }
+
+    #[test]
+    fn caches_unpacked_packages() {
+        let cache = tempfile::tempdir().unwrap();
+        let robot = robot_dir();
+        let vendor = robot.path().join(VENDOR_DIR);
+        wheel(&vendor, "pure-1.0-py3-none-any.whl", &[("pure/a.py", "1")]);
+
+        let first = prepare(robot.path(), Some(cache.path())).unwrap().unwrap();
+        let second = prepare(robot.path(), Some(cache.path())).unwrap().unwrap();
+        assert_eq!(first.path, second.path);
+        assert!(first.path.starts_with(cache.path()));
+        assert_eq!(fs::read_dir(cache.path()).unwrap().count(), 1);
+
+        // a changed package is unpacked again
+        wheel(&vendor, "pure-1.0-py3-none-any.whl", &[("pure/a.py", "2")]);
+        let changed = prepare(robot.path(), Some(cache.path())).unwrap().unwrap();
+        assert_ne!(changed.path, first.path);
+        assert_eq!(
+            fs::read_to_string(changed.path.join("pure/a.py")).unwrap(),
+            "2"
+        );
+    }
+}